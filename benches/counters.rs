@@ -0,0 +1,51 @@
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicI64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use concurrency::StripedCounter;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+// 每个线程做 iters 次加法, 计时包括所有线程
+fn contended(threads: usize, iters: u64, inc: impl Fn() + Sync) -> Duration {
+    let start = Instant::now();
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                for _ in 0..iters {
+                    inc();
+                }
+            });
+        }
+    });
+    start.elapsed()
+}
+
+fn bench_counters(c: &mut Criterion) {
+    let max = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    let mut group = c.benchmark_group("contended_inc");
+    for threads in [1, 2, 4, 8].into_iter().filter(|&t| t <= max.max(2)) {
+        group.bench_with_input(BenchmarkId::new("atomic", threads), &threads, |b, &t| {
+            let counter = AtomicI64::new(0);
+            b.iter_custom(|iters| {
+                contended(t, iters, || {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                })
+            });
+            black_box(counter.load(Ordering::Relaxed));
+        });
+        group.bench_with_input(BenchmarkId::new("striped", threads), &threads, |b, &t| {
+            let counter = StripedCounter::new();
+            b.iter_custom(|iters| contended(t, iters, || counter.inc()));
+            black_box(counter.get());
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_counters);
+criterion_main!(benches);
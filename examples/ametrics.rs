@@ -1,5 +1,5 @@
 use anyhow::Result;
-use concurrency::AmapMetrics;
+use concurrency::{AmapMetrics, TypedMetrics};
 use rand::Rng;
 use std::{
     thread,
@@ -39,13 +39,15 @@ fn main() -> Result<()> {
 }
 
 fn task_worker(idx: usize, metrics: AmapMetrics) -> Result<()> {
+    // 提前拿到句柄, 循环里不用再查 key
+    let calls = metrics.gauge_handle(&format!("call.thread.worker.{}", idx))?;
     thread::spawn(move || {
         loop {
             // do long term stuff
             let mut rng = rand::thread_rng();
 
             thread::sleep(Duration::from_millis(rng.gen_range(100..5000)));
-            metrics.inc(format!("call.thread.worker.{}", idx))?;
+            calls.inc();
         }
         #[allow(unreachable_code)]
         Ok::<_, anyhow::Error>(())
@@ -4,7 +4,7 @@ use std::{
 };
 
 use anyhow::Result;
-use concurrency::CmapMetrics;
+use concurrency::{CmapMetrics, OverflowPolicy};
 use rand::Rng;
 
 const N: usize = 2;
@@ -52,7 +52,8 @@ const M: usize = 4;
 
 // region:    --- DashMap version
 fn main() -> Result<()> {
-    let metrics = CmapMetrics::new();
+    // 页面有 256 个, 最多 128 个 series (包括 __overflow__), 放不下的记到 __overflow__ 里
+    let metrics = CmapMetrics::new().max_cardinality(128, OverflowPolicy::OverflowKey);
     let start_time = Instant::now();
     println!("{}", metrics);
 
@@ -72,11 +73,13 @@ fn main() -> Result<()> {
 
 fn task_worker(idx: usize, metrics: CmapMetrics) -> Result<()> {
     thread::spawn(move || {
+        // 标签固定, 先拿到 counter, 循环里直接加
+        let calls = metrics.counter("call.thread", &[("worker", &idx.to_string())])?;
         loop {
             let mut rng = rand::thread_rng();
             thread::sleep(Duration::from_millis(rng.gen_range(100..5000)));
-            // metrics.inc(format!("call.thread.worker.{}", idx)).unwrap();
-            metrics.inc(format!("call.thread.worker.{}", idx))?;
+            // metrics.inc(format!("call.thread.worker.{}", idx))?;
+            calls.inc();
         }
         #[allow(unreachable_code)]
         Ok::<_, anyhow::Error>(())
@@ -89,8 +92,8 @@ fn request_worker(metrics: CmapMetrics) -> Result<()> {
         loop {
             let mut rng = rand::thread_rng();
             thread::sleep(Duration::from_millis(rng.gen_range(50..800)));
-            let page = rng.gen_range(1..=256);
-            metrics.inc(format!("req.page.{}", page))?;
+            let page = rng.gen_range(1..=256).to_string();
+            metrics.counter("req", &[("page", &page)])?.inc();
         }
         #[allow(unreachable_code)]
         Ok::<_, anyhow::Error>(())
@@ -1,4 +1,12 @@
 use anyhow::Result;
+use concurrency::{multiply, Matrix};
+use rand::distributions::Uniform;
+
 fn main() -> Result<()> {
+    let a = Matrix::random_seeded(3, 4, Uniform::new(0, 10), 42);
+    let b = Matrix::random_seeded(4, 2, Uniform::new(0, 10), 43);
+    println!("a: {:?}", a);
+    println!("b: {:?}", b);
+    println!("a * b: {:?}", multiply(&a, &b)?);
     Ok(())
 }
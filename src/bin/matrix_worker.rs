@@ -0,0 +1,20 @@
+use anyhow::Result;
+use concurrency::serve_worker;
+use tokio::net::TcpListener;
+
+// usage: matrix_worker [addr], 默认监听 127.0.0.1:7878
+// 使用端口 0 时由系统分配端口, 实际地址打印在 stdout 的第一行
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .init();
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "127.0.0.1:7878".to_string());
+
+    let listener = TcpListener::bind(&addr).await?;
+    println!("listening on {}", listener.local_addr()?);
+    serve_worker(listener).await
+}
@@ -0,0 +1,7 @@
+mod coordinator;
+mod protocol;
+mod worker;
+
+pub use coordinator::*;
+pub use protocol::{TileJob, TileResult, WireElement};
+pub use worker::*;
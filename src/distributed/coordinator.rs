@@ -0,0 +1,241 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+};
+
+use anyhow::{anyhow, Result};
+use tokio::{net::TcpStream, sync::mpsc};
+use tracing::{info, warn};
+
+use super::protocol::{read_frame, write_frame, Frame, TileJob, TileResult, WireElement};
+use crate::{Layout, Matrix};
+
+const TILE_SIZE: usize = 64;
+
+/// Shards the map phase of `multiply` across remote workers
+///
+/// The output is cut into `tile_size x tile_size` tiles. Each worker gets one
+/// tile at a time over its own connection; a tile in flight on a worker that
+/// disconnects is put back in the queue for the remaining workers.
+#[derive(Debug, Clone)]
+pub struct Coordinator {
+    workers: Vec<SocketAddr>,
+    tile_size: usize,
+}
+
+// 每个 worker 连接对应一个 task, 通过 Event 把状态汇报给调度循环
+enum Event<T> {
+    Idle(usize),
+    Done(usize, TileResult<T>),
+    /// the worker computed the tile but reported an error, retrying won't help
+    Failed(anyhow::Error),
+    /// the connection broke, the tile in flight (if any) must be reassigned
+    Lost(usize, Option<TileJob<T>>, anyhow::Error),
+}
+
+// 一个 tile 在结果矩阵中的位置
+struct Placement {
+    row_start: usize,
+    col_start: usize,
+}
+
+// region:    --- impls
+impl Coordinator {
+    pub fn new(workers: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self {
+            workers: workers.into_iter().collect(),
+            tile_size: TILE_SIZE,
+        }
+    }
+
+    /// Set the edge length of the output tiles, at least one
+    pub fn tile_size(mut self, tile_size: usize) -> Self {
+        self.tile_size = tile_size.max(1);
+        self
+    }
+
+    /// Multiply `a` and `b` on the workers, the result is row-major
+    pub async fn multiply<T: WireElement>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+    ) -> Result<Matrix<T>> {
+        if a.col() != b.row() {
+            return Err(anyhow!("Matrix multiply error: a.col != b.row"));
+        }
+        if self.workers.is_empty() {
+            return Err(anyhow!("Coordinator error: no workers"));
+        }
+
+        // map phase: cut the output into tiles
+        let (mut pending, placements) = self.tile_jobs(a, b);
+        let total = placements.len();
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let mut job_txs = HashMap::new();
+        for (id, &addr) in self.workers.iter().enumerate() {
+            let (job_tx, job_rx) = mpsc::channel(1);
+            job_txs.insert(id, job_tx);
+            tokio::spawn(run_worker(id, addr, job_rx, event_tx.clone()));
+        }
+        drop(event_tx);
+
+        // reduce phase: place finished tiles, hand out pending ones to idle workers
+        let mut data = vec![T::default(); a.row() * b.col()];
+        let mut done = 0;
+        let mut idle = VecDeque::new();
+        while done < total {
+            let event = event_rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("Coordinator error: all workers are gone"))?;
+            match event {
+                Event::Idle(id) => idle.push_back(id),
+                Event::Done(id, result) => {
+                    let placement = placements
+                        .get(&result.id)
+                        .ok_or_else(|| anyhow!("Coordinator error: unknown tile {}", result.id))?;
+                    for (r, row) in result.data.chunks_exact(result.cols).enumerate() {
+                        let start = (placement.row_start + r) * b.col() + placement.col_start;
+                        data[start..start + result.cols].copy_from_slice(row);
+                    }
+                    done += 1;
+                    idle.push_back(id);
+                }
+                Event::Failed(e) => return Err(e),
+                Event::Lost(id, job, e) => {
+                    warn!("Worker {} lost: {:#}", self.workers[id], e);
+                    job_txs.remove(&id);
+                    if let Some(job) = job {
+                        info!("Reassigning tile {}", job.id);
+                        pending.push_front(job);
+                    }
+                    if job_txs.is_empty() {
+                        return Err(anyhow!(
+                            "Coordinator error: all workers are gone, {} tiles left",
+                            total - done
+                        ));
+                    }
+                }
+            }
+            while !pending.is_empty() {
+                let Some(id) = idle.pop_front() else { break };
+                let Some(job_tx) = job_txs.get(&id) else {
+                    continue;
+                };
+                if let Some(job) = pending.pop_front() {
+                    if let Err(e) = job_tx.send(job).await {
+                        // worker task 已经退出, Lost 事件会随后到达
+                        pending.push_front(e.0);
+                    }
+                }
+            }
+        }
+        // 关闭所有 job channel, worker task 会断开连接并退出
+        drop(job_txs);
+
+        Ok(Matrix::with_layout(
+            data,
+            a.row(),
+            b.col(),
+            Layout::RowMajor,
+        ))
+    }
+
+    fn tile_jobs<T: WireElement>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+    ) -> (VecDeque<TileJob<T>>, HashMap<u64, Placement>) {
+        let mut jobs = VecDeque::new();
+        let mut placements = HashMap::new();
+        for row_start in (0..a.row()).step_by(self.tile_size) {
+            let rows = self.tile_size.min(a.row() - row_start);
+            let panel_a = (row_start..row_start + rows)
+                .flat_map(|i| a.row_vector(i).into_vec())
+                .collect::<Vec<_>>();
+            for col_start in (0..b.col()).step_by(self.tile_size) {
+                let cols = self.tile_size.min(b.col() - col_start);
+                let panel_b = (col_start..col_start + cols)
+                    .flat_map(|j| b.col_vector(j).into_vec())
+                    .collect::<Vec<_>>();
+                let id = jobs.len() as u64;
+                jobs.push_back(TileJob {
+                    id,
+                    rows,
+                    cols,
+                    inner: a.col(),
+                    a: panel_a.clone(),
+                    b: panel_b,
+                });
+                placements.insert(
+                    id,
+                    Placement {
+                        row_start,
+                        col_start,
+                    },
+                );
+            }
+        }
+        (jobs, placements)
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+async fn run_worker<T: WireElement>(
+    id: usize,
+    addr: SocketAddr,
+    mut jobs: mpsc::Receiver<TileJob<T>>,
+    events: mpsc::UnboundedSender<Event<T>>,
+) {
+    let mut stream = match TcpStream::connect(addr).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            let _ = events.send(Event::Lost(id, None, e.into()));
+            return;
+        }
+    };
+    if events.send(Event::Idle(id)).is_err() {
+        return;
+    }
+    while let Some(job) = jobs.recv().await {
+        let event = match round_trip(&mut stream, &job).await {
+            Ok(Ok(result)) => Event::Done(id, result),
+            Ok(Err(e)) => Event::Failed(e),
+            Err(e) => {
+                let _ = events.send(Event::Lost(id, Some(job), e));
+                return;
+            }
+        };
+        if events.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+// 外层 Result 是连接错误, 内层 Result 是 worker 报告的任务错误
+async fn round_trip<T: WireElement>(
+    stream: &mut TcpStream,
+    job: &TileJob<T>,
+) -> Result<Result<TileResult<T>>> {
+    write_frame(stream, &job.encode()).await?;
+    let payload = read_frame(stream)
+        .await?
+        .ok_or_else(|| anyhow!("worker closed the connection"))?;
+    match Frame::<T>::decode(&payload)? {
+        Frame::Result(result) if result.id == job.id => {
+            if result.rows != job.rows || result.cols != job.cols {
+                return Err(anyhow!("worker returned a tile of the wrong shape"));
+            }
+            Ok(Ok(result))
+        }
+        Frame::Result(result) => Err(anyhow!("worker returned tile {} for {}", result.id, job.id)),
+        Frame::Error { message, .. } => Ok(Err(anyhow!(
+            "Worker error: tile {} failed: {}",
+            job.id,
+            message
+        ))),
+        Frame::Job(_) => Err(anyhow!("Protocol error: coordinator expects result frames")),
+    }
+}
+// endregion: --- functions
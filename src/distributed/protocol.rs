@@ -0,0 +1,329 @@
+// length-prefixed binary protocol between coordinator and workers
+//
+// frame   := len: u32 (big endian) | payload
+// payload := kind: u8 | tag: u8 | id: u64 | body        (little endian)
+// job     := rows: u32 | cols: u32 | inner: u32 | a[rows * inner] | b[cols * inner]
+// result  := rows: u32 | cols: u32 | data[rows * cols]
+// error   := utf-8 message
+
+use std::ops::{AddAssign, Mul};
+
+use anyhow::{anyhow, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// Frames larger than this are rejected instead of allocated
+const MAX_FRAME_LEN: u32 = 256 * 1024 * 1024;
+
+const KIND_JOB: u8 = 1;
+const KIND_RESULT: u8 = 2;
+const KIND_ERROR: u8 = 3;
+
+/// Element types that can travel over the wire
+pub trait WireElement:
+    Copy + Default + AddAssign + Mul<Output = Self> + Send + Sync + 'static
+{
+    const TAG: u8;
+    const SIZE: usize;
+
+    fn put(self, buf: &mut Vec<u8>);
+    fn get(bytes: &[u8]) -> Self;
+}
+
+/// A tile of the map phase: `rows` rows of `a` times `cols` columns of `b`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileJob<T> {
+    pub id: u64,
+    pub rows: usize,
+    pub cols: usize,
+    pub inner: usize,
+    /// row panel, `rows` rows of length `inner`
+    pub a: Vec<T>,
+    /// column panel, `cols` columns of length `inner`
+    pub b: Vec<T>,
+}
+
+/// A computed tile, row-major
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileResult<T> {
+    pub id: u64,
+    pub rows: usize,
+    pub cols: usize,
+    pub data: Vec<T>,
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Frame<T> {
+    Job(TileJob<T>),
+    Result(TileResult<T>),
+    Error { id: u64, message: String },
+}
+
+// region:    --- impls
+macro_rules! impl_wire_element {
+    ($($t:ty => $tag:expr),*) => {
+        $(
+            impl WireElement for $t {
+                const TAG: u8 = $tag;
+                const SIZE: usize = std::mem::size_of::<$t>();
+
+                fn put(self, buf: &mut Vec<u8>) {
+                    buf.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn get(bytes: &[u8]) -> Self {
+                    let mut raw = [0u8; std::mem::size_of::<$t>()];
+                    raw.copy_from_slice(bytes);
+                    <$t>::from_le_bytes(raw)
+                }
+            }
+        )*
+    };
+}
+
+impl_wire_element!(i32 => 1, i64 => 2, f32 => 3, f64 => 4);
+
+impl<T: WireElement> TileJob<T> {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(22 + (self.a.len() + self.b.len()) * T::SIZE);
+        put_header(&mut buf, KIND_JOB, T::TAG, self.id);
+        put_u32(&mut buf, self.rows);
+        put_u32(&mut buf, self.cols);
+        put_u32(&mut buf, self.inner);
+        self.a.iter().chain(&self.b).for_each(|x| x.put(&mut buf));
+        buf
+    }
+}
+
+impl<T: WireElement> TileResult<T> {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(18 + self.data.len() * T::SIZE);
+        put_header(&mut buf, KIND_RESULT, T::TAG, self.id);
+        put_u32(&mut buf, self.rows);
+        put_u32(&mut buf, self.cols);
+        self.data.iter().for_each(|x| x.put(&mut buf));
+        buf
+    }
+}
+
+impl<T: WireElement> Frame<T> {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        match self {
+            Frame::Job(job) => job.encode(),
+            Frame::Result(result) => result.encode(),
+            Frame::Error { id, message } => {
+                let mut buf = Vec::new();
+                put_header(&mut buf, KIND_ERROR, T::TAG, *id);
+                buf.extend_from_slice(message.as_bytes());
+                buf
+            }
+        }
+    }
+
+    pub(crate) fn decode(payload: &[u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(payload);
+        let kind = cursor.u8()?;
+        let tag = cursor.u8()?;
+        let id = cursor.u64()?;
+        if kind == KIND_ERROR {
+            let message = String::from_utf8_lossy(cursor.rest()).into_owned();
+            return Ok(Frame::Error { id, message });
+        }
+        if tag != T::TAG {
+            return Err(anyhow!("Protocol error: element tag {} != {}", tag, T::TAG));
+        }
+        match kind {
+            KIND_JOB => {
+                let (rows, cols, inner) = (cursor.u32()?, cursor.u32()?, cursor.u32()?);
+                let a = cursor.elements(rows * inner)?;
+                let b = cursor.elements(cols * inner)?;
+                cursor.finish()?;
+                Ok(Frame::Job(TileJob {
+                    id,
+                    rows,
+                    cols,
+                    inner,
+                    a,
+                    b,
+                }))
+            }
+            KIND_RESULT => {
+                let (rows, cols) = (cursor.u32()?, cursor.u32()?);
+                let data = cursor.elements(rows * cols)?;
+                cursor.finish()?;
+                Ok(Frame::Result(TileResult {
+                    id,
+                    rows,
+                    cols,
+                    data,
+                }))
+            }
+            _ => Err(anyhow!("Protocol error: unknown frame kind {}", kind)),
+        }
+    }
+}
+
+/// Peek at the element tag and id of a payload, so a worker can pick `T`
+pub(crate) fn peek_header(payload: &[u8]) -> Result<(u8, u64)> {
+    let mut cursor = Cursor::new(payload);
+    cursor.u8()?;
+    Ok((cursor.u8()?, cursor.u64()?))
+}
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.buf.len() < n {
+            return Err(anyhow!("Protocol error: truncated frame"));
+        }
+        let (head, tail) = self.buf.split_at(n);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<usize> {
+        let raw = self.take(4)?;
+        Ok(u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize)
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        let mut raw = [0u8; 8];
+        raw.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(raw))
+    }
+
+    fn elements<T: WireElement>(&mut self, n: usize) -> Result<Vec<T>> {
+        let bytes = self.take(
+            n.checked_mul(T::SIZE)
+                .ok_or_else(|| anyhow!("Protocol error: element count {} overflows", n))?,
+        )?;
+        Ok(bytes.chunks_exact(T::SIZE).map(T::get).collect())
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+
+    fn finish(&self) -> Result<()> {
+        if !self.buf.is_empty() {
+            return Err(anyhow!("Protocol error: {} trailing bytes", self.buf.len()));
+        }
+        Ok(())
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+fn put_header(buf: &mut Vec<u8>, kind: u8, tag: u8, id: u64) {
+    buf.push(kind);
+    buf.push(tag);
+    buf.extend_from_slice(&id.to_le_bytes());
+}
+
+fn put_u32(buf: &mut Vec<u8>, n: usize) {
+    buf.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+/// Read one frame, `None` if the peer closed the connection cleanly
+pub(crate) async fn read_frame<R>(reader: &mut R) -> Result<Option<Vec<u8>>>
+where
+    R: AsyncRead + Unpin,
+{
+    let len = match reader.read_u32().await {
+        Ok(len) => len,
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if len > MAX_FRAME_LEN {
+        return Err(anyhow!(
+            "Protocol error: frame of {} bytes is too large",
+            len
+        ));
+    }
+    let mut payload = vec![0u8; len as usize];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+pub(crate) async fn write_frame<W>(writer: &mut W, payload: &[u8]) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let len = u32::try_from(payload.len())
+        .ok()
+        .filter(|&len| len <= MAX_FRAME_LEN)
+        .ok_or_else(|| {
+            anyhow!(
+                "Protocol error: frame of {} bytes is too large",
+                payload.len()
+            )
+        })?;
+    writer.write_u32(len).await?;
+    writer.write_all(payload).await?;
+    writer.flush().await?;
+    Ok(())
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_job_and_result_round_trip() -> Result<()> {
+        let job = Frame::Job(TileJob {
+            id: 7,
+            rows: 2,
+            cols: 1,
+            inner: 3,
+            a: vec![1.0f64, 2.0, 3.0, 4.0, 5.0, 6.0],
+            b: vec![0.5, 0.25, 0.125],
+        });
+        assert_eq!(Frame::<f64>::decode(&job.encode())?, job);
+
+        let result = Frame::Result(TileResult {
+            id: 7,
+            rows: 2,
+            cols: 1,
+            data: vec![-3i32, 9],
+        });
+        assert_eq!(Frame::<i32>::decode(&result.encode())?, result);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_frames() {
+        let job = Frame::Job(TileJob {
+            id: 1,
+            rows: 1,
+            cols: 1,
+            inner: 2,
+            a: vec![1i64, 2],
+            b: vec![3, 4],
+        })
+        .encode();
+        assert!(Frame::<i64>::decode(&job[..job.len() - 1]).is_err());
+        assert!(Frame::<i32>::decode(&job).is_err());
+        assert_eq!(peek_header(&job).unwrap(), (i64::TAG, 1));
+    }
+
+    #[tokio::test]
+    async fn test_frame_io() -> Result<()> {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        write_frame(&mut client, b"hello").await?;
+        drop(client);
+        assert_eq!(read_frame(&mut server).await?, Some(b"hello".to_vec()));
+        assert_eq!(read_frame(&mut server).await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::net::SocketAddr;
+
+use anyhow::{anyhow, Result};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{info, warn};
+
+use super::protocol::{peek_header, read_frame, write_frame, Frame, TileResult, WireElement};
+use crate::matrix::multiply_tile;
+
+/// Accept coordinator connections forever and compute every tile job they send
+pub async fn serve_worker(listener: TcpListener) -> Result<()> {
+    loop {
+        let (stream, remote_addr) = listener.accept().await?;
+        info!("Accepted coordinator connection from: {}", remote_addr);
+        tokio::spawn(async move {
+            if let Err(e) = process_conn(stream, remote_addr).await {
+                warn!("Error processing conn with {}: {:?}", remote_addr, e);
+            }
+        });
+    }
+}
+
+async fn process_conn(mut stream: TcpStream, remote_addr: SocketAddr) -> Result<()> {
+    while let Some(payload) = read_frame(&mut stream).await? {
+        let (tag, id) = peek_header(&payload)?;
+        // 根据 tag 选择元素类型, 再交给泛型的 handle_job
+        let reply = match tag {
+            <i32 as WireElement>::TAG => handle_job::<i32>(payload).await,
+            <i64 as WireElement>::TAG => handle_job::<i64>(payload).await,
+            <f32 as WireElement>::TAG => handle_job::<f32>(payload).await,
+            <f64 as WireElement>::TAG => handle_job::<f64>(payload).await,
+            tag => Err(anyhow!("Protocol error: unknown element tag {}", tag)),
+        };
+        // 单个任务出错时回复 error frame, 连接继续可用
+        let reply = reply.unwrap_or_else(|e| {
+            warn!("Tile job {} from {} failed: {:#}", id, remote_addr, e);
+            let message = format!("{:#}", e);
+            Frame::<i32>::Error { id, message }.encode()
+        });
+        write_frame(&mut stream, &reply).await?;
+    }
+    info!("Connection {} closed", remote_addr);
+    Ok(())
+}
+
+async fn handle_job<T: WireElement>(payload: Vec<u8>) -> Result<Vec<u8>> {
+    // decode 已经校验了 panel 的长度与 rows/cols/inner 一致
+    let job = match Frame::<T>::decode(&payload)? {
+        Frame::Job(job) => job,
+        _ => return Err(anyhow!("Protocol error: worker expects job frames")),
+    };
+    // 计算是 CPU 密集的, 放到 blocking 线程池里, 不阻塞 tokio 的 worker 线程
+    let result = tokio::task::spawn_blocking(move || TileResult {
+        id: job.id,
+        rows: job.rows,
+        cols: job.cols,
+        data: multiply_tile(&job.a, &job.b, job.rows, job.cols, job.inner),
+    })
+    .await?;
+    Ok(result.encode())
+}
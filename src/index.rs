@@ -0,0 +1,320 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fmt,
+    sync::{PoisonError, RwLock, RwLockReadGuard},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{dot, parallel::par_ranges, Float, Vector};
+
+mod hnsw;
+
+pub use hnsw::*;
+
+/// How query and stored vectors are compared
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    /// cosine similarity, higher is closer
+    #[default]
+    Cosine,
+    /// Euclidean distance, lower is closer
+    L2,
+    /// dot product, higher is closer
+    Dot,
+}
+
+/// One search result
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit<T> {
+    /// id returned by `insert`
+    pub id: usize,
+    /// similarity or distance, depending on the `Metric`
+    pub score: T,
+}
+
+/// A brute-force vector index: concurrent inserts, parallel exact top-k
+pub struct VectorIndex<T> {
+    dim: usize,
+    entries: RwLock<Vec<Entry<T>>>,
+}
+
+// 插入时就算好 L2 范数, 查询时 cosine 只需要一次点积
+struct Entry<T> {
+    vector: Vector<T>,
+    norm: T,
+}
+
+// 统一成 "越小越近" 的 key, BinaryHeap 里距离相等时 id 小的优先
+#[derive(Debug, Clone, Copy)]
+struct Candidate<T> {
+    key: T,
+    id: usize,
+}
+
+// 保留 key 最小的 k 个, 堆顶是当前最差的那个
+struct TopK<T> {
+    k: usize,
+    heap: BinaryHeap<Candidate<T>>,
+}
+
+// region:    --- impls
+impl Metric {
+    fn key<T: Float>(self, a: &Entry<T>, b: &Entry<T>) -> T {
+        let d = || dot(&a.vector, &b.vector).expect("dimension checked on insert");
+        match self {
+            Metric::Dot => -d(),
+            Metric::Cosine if a.norm == T::default() || b.norm == T::default() => T::default(),
+            Metric::Cosine => -(d() / (a.norm * b.norm)),
+            Metric::L2 => a
+                .vector
+                .euclidean_distance(&b.vector)
+                .expect("dimension checked on insert"),
+        }
+    }
+
+    fn score<T: Float>(self, key: T) -> T {
+        match self {
+            Metric::Cosine | Metric::Dot => -key,
+            Metric::L2 => key,
+        }
+    }
+}
+
+impl<T: Float> Entry<T> {
+    fn new(vector: Vector<T>) -> Self {
+        let norm = vector.norm_l2();
+        Self { vector, norm }
+    }
+}
+
+impl<T: Float> VectorIndex<T> {
+    pub fn new(dim: usize) -> Self {
+        Self {
+            dim,
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Still counts after a panic poisoned the lock, when `insert` and
+    /// `search` return an error
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a vector and return its id, safe to call from many threads
+    pub fn insert(&self, vector: Vector<T>) -> Result<usize> {
+        check_dim(self.dim, &vector)?;
+        let entry = Entry::new(vector);
+        let mut entries = self
+            .entries
+            .write()
+            .map_err(|_| anyhow!("VectorIndex error: lock poisoned"))?;
+        entries.push(entry);
+        Ok(entries.len() - 1)
+    }
+
+    /// The `k` closest vectors to `query`, closest first
+    ///
+    /// Scans every stored vector, split across threads for large indexes.
+    pub fn search(&self, query: &Vector<T>, k: usize, metric: Metric) -> Result<Vec<Hit<T>>> {
+        check_dim(self.dim, query)?;
+        let query = Entry::new(Vector::new(query.as_slice()));
+        let entries = self.read()?;
+        let parts = par_ranges(entries.len(), self.dim, |range| {
+            let mut top = TopK::new(k);
+            for id in range {
+                top.push(Candidate::new(metric.key(&query, &entries[id]), id));
+            }
+            top
+        });
+        let mut top = TopK::new(k);
+        for part in parts {
+            for candidate in part.heap {
+                top.push(candidate);
+            }
+        }
+        Ok(top.into_hits(metric))
+    }
+
+    fn read(&self) -> Result<RwLockReadGuard<'_, Vec<Entry<T>>>> {
+        self.entries
+            .read()
+            .map_err(|_| anyhow!("VectorIndex error: lock poisoned"))
+    }
+}
+
+impl<T: Float> fmt::Debug for VectorIndex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VectorIndex")
+            .field("dim", &self.dim)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<T> Candidate<T> {
+    fn new(key: T, id: usize) -> Self {
+        Self { key, id }
+    }
+}
+
+impl<T: PartialOrd> Ord for Candidate<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key
+            .partial_cmp(&other.key)
+            .unwrap_or(Ordering::Equal)
+            .then(self.id.cmp(&other.id))
+    }
+}
+
+impl<T: PartialOrd> PartialOrd for Candidate<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: PartialOrd> PartialEq for Candidate<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: PartialOrd> Eq for Candidate<T> {}
+
+impl<T: Float> TopK<T> {
+    fn new(k: usize) -> Self {
+        Self {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    fn push(&mut self, candidate: Candidate<T>) {
+        if self.heap.len() < self.k {
+            self.heap.push(candidate);
+        } else if self.heap.peek().is_some_and(|worst| candidate < *worst) {
+            self.heap.pop();
+            self.heap.push(candidate);
+        }
+    }
+
+    fn into_hits(self, metric: Metric) -> Vec<Hit<T>> {
+        into_hits(self.heap.into_sorted_vec(), metric)
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+fn check_dim<T>(dim: usize, vector: &Vector<T>) -> Result<()> {
+    if vector.len() != dim {
+        return Err(anyhow!(
+            "VectorIndex error: expected dim {}, got {}",
+            dim,
+            vector.len()
+        ));
+    }
+    Ok(())
+}
+
+// candidates 已经按 key 从小到大排好
+fn into_hits<T: Float>(candidates: Vec<Candidate<T>>, metric: Metric) -> Vec<Hit<T>> {
+    candidates
+        .into_iter()
+        .map(|c| Hit {
+            id: c.id,
+            score: metric.score(c.key),
+        })
+        .collect()
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use rand::distributions::Uniform;
+
+    use super::*;
+
+    #[test]
+    fn test_vector_index_metrics() -> Result<()> {
+        let index = VectorIndex::new(2);
+        index.insert(Vector::new([1.0, 0.0]))?;
+        index.insert(Vector::new([0.0, 2.0]))?;
+        index.insert(Vector::new([3.0, 3.0]))?;
+        let query = Vector::new([1.0, 0.1]);
+
+        let hits = index.search(&query, 2, Metric::Cosine)?;
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![0, 2]);
+        assert!(hits[0].score > hits[1].score);
+
+        let hits = index.search(&query, 1, Metric::L2)?;
+        assert_eq!(hits[0].id, 0);
+        assert!((hits[0].score - 0.1).abs() < 1e-9);
+
+        let hits = index.search(&query, 5, Metric::Dot)?;
+        assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![2, 0, 1]);
+        assert!((hits[0].score - 3.3).abs() < 1e-9);
+
+        assert!(index.insert(Vector::new([1.0])).is_err());
+        assert!(index.search(&Vector::new([1.0]), 1, Metric::L2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_index_concurrent_inserts() -> Result<()> {
+        let index = VectorIndex::<f32>::new(16);
+        let dist = Uniform::new(-1.0f32, 1.0);
+        thread::scope(|s| {
+            for t in 0..4 {
+                let index = &index;
+                s.spawn(move || {
+                    for i in 0..500 {
+                        let v = Vector::random_seeded(16, dist, t * 1000 + i);
+                        index.insert(v).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(index.len(), 2000);
+
+        // 查询一个已经插入的向量, 最近的一定是它自己
+        let query = Vector::random_seeded(16, dist, 3 * 1000 + 42);
+        let hits = index.search(&query, 3, Metric::L2)?;
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].score, 0.0);
+        assert!(hits[0].score <= hits[1].score && hits[1].score <= hits[2].score);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_index_poisoned_lock() -> Result<()> {
+        let index = VectorIndex::new(1);
+        index.insert(Vector::new([1.0]))?;
+        // 持有写锁的线程 panic, 锁被标记为 poisoned
+        let _ = thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = index.entries.write();
+                panic!("poison the lock");
+            })
+            .join()
+        });
+        assert_eq!(index.len(), 1);
+        assert!(index.insert(Vector::new([2.0])).is_err());
+        assert!(index.search(&Vector::new([1.0]), 1, Metric::L2).is_err());
+        Ok(())
+    }
+}
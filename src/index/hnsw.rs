@@ -0,0 +1,322 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashSet},
+    fmt,
+    sync::{PoisonError, RwLock},
+};
+
+use anyhow::{anyhow, Result};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{check_dim, into_hits, Candidate, Entry, Hit, Metric};
+use crate::{Float, Vector};
+
+const DEFAULT_M: usize = 16;
+const DEFAULT_EF_CONSTRUCTION: usize = 100;
+const DEFAULT_EF_SEARCH: usize = 50;
+
+/// An approximate nearest-neighbour index (hierarchical navigable small world)
+///
+/// Every vector is a node in a stack of proximity graphs; upper layers are
+/// sparse and let a search jump close to the query before it walks the dense
+/// bottom layer. Much faster than `VectorIndex` on large sets, but the top-k
+/// may miss some true neighbours. Inserts take a write lock, searches run
+/// concurrently.
+pub struct HnswIndex<T> {
+    dim: usize,
+    metric: Metric,
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    graph: RwLock<Graph<T>>,
+}
+
+struct Graph<T> {
+    nodes: Vec<Node<T>>,
+    entry: Option<usize>,
+    max_level: usize,
+    rng: StdRng,
+}
+
+struct Node<T> {
+    entry: Entry<T>,
+    /// neighbours on each layer, `links[0]` is the bottom layer
+    links: Vec<Vec<usize>>,
+}
+
+// region:    --- impls
+impl<T: Float> HnswIndex<T> {
+    pub fn new(dim: usize, metric: Metric) -> Self {
+        Self {
+            dim,
+            metric,
+            m: DEFAULT_M,
+            ef_construction: DEFAULT_EF_CONSTRUCTION,
+            ef_search: DEFAULT_EF_SEARCH,
+            graph: RwLock::new(Graph {
+                nodes: Vec::new(),
+                entry: None,
+                max_level: 0,
+                rng: StdRng::from_entropy(),
+            }),
+        }
+    }
+
+    /// Neighbours kept per node and layer (twice as many on the bottom layer)
+    pub fn m(mut self, m: usize) -> Self {
+        self.m = m.max(2);
+        self
+    }
+
+    /// Candidate list size while inserting, higher builds a better graph
+    pub fn ef_construction(mut self, ef: usize) -> Self {
+        self.ef_construction = ef.max(1);
+        self
+    }
+
+    /// Candidate list size while searching, higher gives better recall
+    pub fn ef_search(mut self, ef: usize) -> Self {
+        self.ef_search = ef.max(1);
+        self
+    }
+
+    /// Seed the level generator so the same inserts build the same graph
+    pub fn seed(mut self, seed: u64) -> Self {
+        let graph = self.graph.get_mut().unwrap_or_else(PoisonError::into_inner);
+        graph.rng = StdRng::seed_from_u64(seed);
+        self
+    }
+
+    /// Like `VectorIndex::len`, still counts after a panic poisoned the lock
+    pub fn len(&self) -> usize {
+        self.graph
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .nodes
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, vector: Vector<T>) -> Result<usize> {
+        check_dim(self.dim, &vector)?;
+        let mut graph = self
+            .graph
+            .write()
+            .map_err(|_| anyhow!("HnswIndex error: lock poisoned"))?;
+        let graph = &mut *graph;
+
+        // 层数服从几何分布, 每往上一层节点数大约变成 1/m
+        let ml = 1.0 / (self.m as f64).ln();
+        let level = (-graph.rng.gen::<f64>().max(f64::MIN_POSITIVE).ln() * ml) as usize;
+        let id = graph.nodes.len();
+        graph.nodes.push(Node {
+            entry: Entry::new(vector),
+            links: vec![Vec::new(); level + 1],
+        });
+        let Some(mut ep) = graph.entry else {
+            graph.entry = Some(id);
+            graph.max_level = level;
+            return Ok(id);
+        };
+
+        let query = &graph.nodes[id].entry;
+        for layer in (level + 1..=graph.max_level).rev() {
+            ep = self.greedy(&graph.nodes, query, ep, layer);
+        }
+        let mut links = Vec::with_capacity(level + 1);
+        for layer in (0..=level.min(graph.max_level)).rev() {
+            let found = self.search_layer(&graph.nodes, query, ep, self.ef_construction, layer);
+            ep = found[0].id;
+            links.push((
+                layer,
+                found
+                    .iter()
+                    .take(self.max_links(layer))
+                    .map(|c| c.id)
+                    .collect::<Vec<_>>(),
+            ));
+        }
+        for (layer, neighbours) in links {
+            for &n in &neighbours {
+                graph.nodes[n].links[layer].push(id);
+                self.prune(&mut graph.nodes, n, layer);
+            }
+            graph.nodes[id].links[layer] = neighbours;
+        }
+        if level > graph.max_level {
+            graph.entry = Some(id);
+            graph.max_level = level;
+        }
+        Ok(id)
+    }
+
+    /// Approximately the `k` closest vectors to `query`, closest first
+    pub fn search(&self, query: &Vector<T>, k: usize) -> Result<Vec<Hit<T>>> {
+        check_dim(self.dim, query)?;
+        let graph = self
+            .graph
+            .read()
+            .map_err(|_| anyhow!("HnswIndex error: lock poisoned"))?;
+        let Some(mut ep) = graph.entry else {
+            return Ok(Vec::new());
+        };
+        let query = Entry::new(Vector::new(query.as_slice()));
+        for layer in (1..=graph.max_level).rev() {
+            ep = self.greedy(&graph.nodes, &query, ep, layer);
+        }
+        let mut found = self.search_layer(&graph.nodes, &query, ep, self.ef_search.max(k), 0);
+        found.truncate(k);
+        Ok(into_hits(found, self.metric))
+    }
+
+    fn max_links(&self, layer: usize) -> usize {
+        if layer == 0 {
+            self.m * 2
+        } else {
+            self.m
+        }
+    }
+
+    fn key(&self, nodes: &[Node<T>], query: &Entry<T>, id: usize) -> Candidate<T> {
+        Candidate::new(self.metric.key(query, &nodes[id].entry), id)
+    }
+
+    // 在一层上贪心地走向更近的邻居, 直到走不动
+    fn greedy(&self, nodes: &[Node<T>], query: &Entry<T>, mut ep: usize, layer: usize) -> usize {
+        let mut best = self.key(nodes, query, ep);
+        loop {
+            let next = nodes[ep].links[layer]
+                .iter()
+                .map(|&n| self.key(nodes, query, n))
+                .min()
+                .filter(|c| *c < best);
+            match next {
+                Some(c) => {
+                    ep = c.id;
+                    best = c;
+                }
+                None => return ep,
+            }
+        }
+    }
+
+    // 一层上的 best-first 搜索, 返回最近的 ef 个, 按距离从小到大
+    fn search_layer(
+        &self,
+        nodes: &[Node<T>],
+        query: &Entry<T>,
+        ep: usize,
+        ef: usize,
+        layer: usize,
+    ) -> Vec<Candidate<T>> {
+        let mut visited = HashSet::from([ep]);
+        let start = self.key(nodes, query, ep);
+        let mut frontier = BinaryHeap::from([Reverse(start)]);
+        let mut found = BinaryHeap::from([start]);
+        while let Some(Reverse(current)) = frontier.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|worst| current > *worst) {
+                break;
+            }
+            for &n in &nodes[current.id].links[layer] {
+                if !visited.insert(n) {
+                    continue;
+                }
+                let c = self.key(nodes, query, n);
+                if found.len() < ef || found.peek().is_some_and(|worst| c < *worst) {
+                    frontier.push(Reverse(c));
+                    found.push(c);
+                    if found.len() > ef {
+                        found.pop();
+                    }
+                }
+            }
+        }
+        found.into_sorted_vec()
+    }
+
+    // 邻居超过上限时只保留离 id 最近的那些
+    fn prune(&self, nodes: &mut [Node<T>], id: usize, layer: usize) {
+        let max = self.max_links(layer);
+        if nodes[id].links[layer].len() <= max {
+            return;
+        }
+        let node = &nodes[id].entry;
+        let mut neighbours = nodes[id].links[layer]
+            .iter()
+            .map(|&n| Candidate::new(self.metric.key(node, &nodes[n].entry), n))
+            .collect::<Vec<_>>();
+        neighbours.sort();
+        nodes[id].links[layer] = neighbours.into_iter().take(max).map(|c| c.id).collect();
+    }
+}
+
+impl<T: Float> fmt::Debug for HnswIndex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HnswIndex")
+            .field("dim", &self.dim)
+            .field("metric", &self.metric)
+            .field("m", &self.m)
+            .field("ef_construction", &self.ef_construction)
+            .field("ef_search", &self.ef_search)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::Uniform;
+
+    use super::*;
+    use crate::VectorIndex;
+
+    #[test]
+    fn test_hnsw_recall_against_brute_force() -> Result<()> {
+        let (dim, n, k) = (8, 600, 10);
+        let dist = Uniform::new(-1.0f32, 1.0);
+        for metric in [Metric::Cosine, Metric::L2] {
+            let exact = VectorIndex::new(dim);
+            let hnsw = HnswIndex::new(dim, metric).m(8).ef_construction(40).seed(7);
+            for i in 0..n {
+                exact.insert(Vector::random_seeded(dim, dist, i))?;
+                hnsw.insert(Vector::random_seeded(dim, dist, i))?;
+            }
+            assert_eq!(hnsw.len(), n as usize);
+
+            let mut hits = 0;
+            for q in 0..20 {
+                let query = Vector::random_seeded(dim, dist, 100_000 + q);
+                let truth = exact
+                    .search(&query, k, metric)?
+                    .into_iter()
+                    .map(|h| h.id)
+                    .collect::<HashSet<_>>();
+                let approx = hnsw.search(&query, k)?;
+                assert_eq!(approx.len(), k);
+                hits += approx.iter().filter(|h| truth.contains(&h.id)).count();
+            }
+            let recall = hits as f64 / (20 * k) as f64;
+            assert!(recall >= 0.9, "{:?} recall {}", metric, recall);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_hnsw_small_and_empty() -> Result<()> {
+        let index = HnswIndex::new(2, Metric::Dot);
+        assert!(index.search(&Vector::new([1.0, 0.0]), 3)?.is_empty());
+        index.insert(Vector::new([1.0, 0.0]))?;
+        index.insert(Vector::new([0.0, 1.0]))?;
+        let hits = index.search(&Vector::new([2.0, 1.0]), 3)?;
+        assert_eq!(
+            hits,
+            vec![Hit { id: 0, score: 2.0 }, Hit { id: 1, score: 1.0 }]
+        );
+        assert!(index.insert(Vector::new([1.0])).is_err());
+        Ok(())
+    }
+}
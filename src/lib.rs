@@ -1,7 +1,21 @@
+mod distributed;
+mod index;
+mod mapreduce;
 mod matrix;
 mod metrics;
+mod num;
+mod parallel;
+mod scheduler;
+mod simd;
+mod summation;
 mod vector;
 
+pub use distributed::*;
+pub use index::*;
+pub use mapreduce::*;
 pub use matrix::*;
 pub use metrics::*;
+pub use num::Float;
+pub use scheduler::*;
+pub use summation::*;
 pub use vector::*;
@@ -0,0 +1,366 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{Scheduler, SchedulerStats, WorkStealingPool};
+
+const NUM_THREADS: usize = 4;
+
+type MapFn<I, O> = dyn Fn(I) -> Result<O> + Send + Sync;
+type PartitionFn = dyn Fn(usize, usize) -> usize + Send + Sync;
+// worker 线程结束时返回 (任务数, 结束时间)
+type WorkerHandle = thread::JoinHandle<(usize, Duration)>;
+
+/// A small data-parallel map/reduce job
+///
+/// Every input carries an index. The partitioner picks the worker thread for
+/// an index, the map function runs on that worker, and the reducer folds the
+/// index-addressed outputs back on the calling thread.
+pub struct MapReduce<I, O> {
+    num_threads: usize,
+    scheduler: Scheduler,
+    map: Arc<MapFn<I, O>>,
+    partitioner: Arc<PartitionFn>,
+}
+
+struct MsgOutput<O> {
+    idx: usize,
+    value: Result<O>,
+}
+
+struct Msg<I, O> {
+    idx: usize,
+    input: I,
+    /// sender to send the result back
+    sender: oneshot::Sender<MsgOutput<O>>,
+}
+
+// region:    --- impls
+impl<I, O> MapReduce<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    pub fn new(map: impl Fn(I) -> Result<O> + Send + Sync + 'static) -> Self {
+        Self {
+            num_threads: NUM_THREADS,
+            scheduler: Scheduler::Static,
+            map: Arc::new(map),
+            // 默认按照 idx 取模分配到各个线程
+            partitioner: Arc::new(|idx, num_threads| idx % num_threads),
+        }
+    }
+
+    /// Set the number of worker threads, at least one
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads.max(1);
+        self
+    }
+
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Set the partitioner, `(idx, num_threads) -> worker`
+    pub fn partitioner(
+        mut self,
+        partitioner: impl Fn(usize, usize) -> usize + Send + Sync + 'static,
+    ) -> Self {
+        self.partitioner = Arc::new(partitioner);
+        self
+    }
+
+    /// Run the job and fold every output into `init` with `reduce`
+    ///
+    /// Outputs arrive in input order. The first map error aborts the reduce
+    /// phase and is returned; with either scheduler, inputs not mapped yet are
+    /// skipped, and every worker thread has exited before `run` returns.
+    pub fn run<A>(
+        &self,
+        inputs: impl IntoIterator<Item = (usize, I)>,
+        init: A,
+        reduce: impl FnMut(&mut A, usize, O),
+    ) -> Result<A> {
+        Ok(self.run_with_stats(inputs, init, reduce)?.0)
+    }
+
+    /// Same as `run`, also reporting what the workers did
+    pub fn run_with_stats<A>(
+        &self,
+        inputs: impl IntoIterator<Item = (usize, I)>,
+        init: A,
+        reduce: impl FnMut(&mut A, usize, O),
+    ) -> Result<(A, SchedulerStats)> {
+        match self.scheduler {
+            Scheduler::Static => self.run_static(inputs, init, reduce),
+            Scheduler::WorkStealing => self.run_work_stealing(inputs, init, reduce),
+        }
+    }
+
+    fn run_static<A>(
+        &self,
+        inputs: impl IntoIterator<Item = (usize, I)>,
+        init: A,
+        mut reduce: impl FnMut(&mut A, usize, O),
+    ) -> Result<(A, SchedulerStats)> {
+        let start = Instant::now();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let (senders, handles) = self.spawn_workers(start, &cancelled);
+
+        // map/reduce: map phase
+        let mut error = None;
+        let mut receivers = Vec::new();
+        for (idx, input) in inputs {
+            let worker = (self.partitioner)(idx, self.num_threads) % self.num_threads;
+            let (tx, rx) = oneshot::channel();
+            if senders[worker].send(Msg::new(idx, input, tx)).is_err() {
+                error = Some(anyhow!("MapReduce error: worker {} is gone", worker));
+                break;
+            }
+            receivers.push(rx);
+        }
+        // 关闭 channel, 所有任务完成后 worker 线程会自动退出
+        drop(senders);
+
+        // map/reduce: reduce phase
+        let mut acc = init;
+        for rx in receivers {
+            if error.is_some() {
+                break;
+            }
+            match rx.recv() {
+                Ok(MsgOutput { idx, value: Ok(v) }) => reduce(&mut acc, idx, v),
+                Ok(MsgOutput { value: Err(e), .. }) => error = Some(e),
+                Err(_) => error = Some(anyhow!("MapReduce error: worker panicked")),
+            }
+        }
+        // 出错之后剩下的任务不用再算了, worker 直接丢掉它们
+        if error.is_some() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+
+        // 无论成功与否都等 worker 线程退出, 不留下后台线程
+        let joined = handles.into_iter().map(|h| h.join()).collect::<Vec<_>>();
+        if let Some(e) = error {
+            return Err(e);
+        }
+        let finished = joined
+            .into_iter()
+            .map(|r| r.map_err(|_| anyhow!("MapReduce error: worker panicked")))
+            .collect::<Result<Vec<_>>>()?;
+        let elapsed = start.elapsed();
+        let stats = SchedulerStats {
+            tasks: finished.iter().map(|(tasks, _)| *tasks).collect(),
+            steals: 0,
+            idle: finished
+                .iter()
+                .map(|(_, at)| elapsed.saturating_sub(*at))
+                .sum(),
+            elapsed,
+        };
+        Ok((acc, stats))
+    }
+
+    fn run_work_stealing<A>(
+        &self,
+        inputs: impl IntoIterator<Item = (usize, I)>,
+        init: A,
+        mut reduce: impl FnMut(&mut A, usize, O),
+    ) -> Result<(A, SchedulerStats)> {
+        let map = &self.map;
+        let pool = WorkStealingPool::new(self.num_threads);
+        // 有任务出错之后, 还没开始的任务直接跳过, 不再调用 map
+        let failed = AtomicBool::new(false);
+        let (outputs, stats) = pool.run(inputs, |(idx, input)| {
+            if failed.load(Ordering::Relaxed) {
+                return (idx, None);
+            }
+            let value = map(input);
+            if value.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            (idx, Some(value))
+        })?;
+
+        // 跳过的任务之前一定有出错的任务, 按输入顺序返回第一个错误
+        let mut acc = init;
+        for (idx, value) in outputs {
+            if let Some(value) = value {
+                reduce(&mut acc, idx, value?);
+            }
+        }
+        Ok((acc, stats))
+    }
+
+    fn spawn_workers(
+        &self,
+        start: Instant,
+        cancelled: &Arc<AtomicBool>,
+    ) -> (Vec<mpsc::Sender<Msg<I, O>>>, Vec<WorkerHandle>) {
+        (0..self.num_threads)
+            .map(|_| {
+                let (tx, rx) = mpsc::channel::<Msg<I, O>>();
+                let map = Arc::clone(&self.map);
+                let cancelled = Arc::clone(cancelled);
+                let handle = thread::spawn(move || {
+                    let mut tasks = 0;
+                    for msg in rx {
+                        if cancelled.load(Ordering::Relaxed) {
+                            continue;
+                        }
+                        let value = map(msg.input);
+                        tasks += 1;
+                        // 接收方只有在出错提前结束时才会被丢掉, 结果没人要了, 忽略即可
+                        let _ = msg.sender.send(MsgOutput::new(msg.idx, value));
+                    }
+                    (tasks, start.elapsed())
+                });
+                (tx, handle)
+            })
+            .unzip()
+    }
+}
+
+impl<I, O> fmt::Debug for MapReduce<I, O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapReduce")
+            .field("num_threads", &self.num_threads)
+            .field("scheduler", &self.scheduler)
+            .finish()
+    }
+}
+
+impl<O> MsgOutput<O> {
+    fn new(idx: usize, value: Result<O>) -> Self {
+        Self { idx, value }
+    }
+}
+
+impl<I, O> Msg<I, O> {
+    fn new(idx: usize, input: I, sender: oneshot::Sender<MsgOutput<O>>) -> Self {
+        Self { idx, input, sender }
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn test_map_reduce_sum_of_squares() -> Result<()> {
+        let job = MapReduce::new(|x: u64| Ok(x * x));
+        let inputs = (0..100).map(|i| (i as usize, i));
+        let sum = job.run(inputs, 0, |acc, _, value| *acc += value)?;
+        assert_eq!(sum, (0..100u64).map(|x| x * x).sum());
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_reduce_index_addressed_outputs() -> Result<()> {
+        let job = MapReduce::new(|s: &'static str| Ok(s.len())).num_threads(3);
+        let words = ["a", "bb", "ccc", "dddd"];
+        let lens = job.run(
+            words.into_iter().enumerate(),
+            vec![0; words.len()],
+            |acc, idx, len| acc[idx] = len,
+        )?;
+        assert_eq!(lens, vec![1, 2, 3, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_reduce_custom_partitioner() -> Result<()> {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let job = MapReduce::new(|x: usize| Ok(x + 1))
+            .num_threads(2)
+            .partitioner(|_, _| {
+                CALLS.fetch_add(1, Ordering::Relaxed);
+                0
+            });
+        let sum = job.run((0..10).map(|i| (i, i)), 0, |acc, _, v| *acc += v)?;
+        assert_eq!(sum, 55);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_reduce_work_stealing() -> Result<()> {
+        let job = MapReduce::new(|x: u64| Ok(x * x)).scheduler(Scheduler::WorkStealing);
+        let (squares, stats) =
+            job.run_with_stats((0..100).map(|i| (i as usize, i)), vec![], |acc, idx, v| {
+                acc.push((idx, v))
+            })?;
+        let expected = (0..100u64).map(|x| (x as usize, x * x)).collect::<Vec<_>>();
+        assert_eq!(squares, expected);
+        assert_eq!(stats.tasks.iter().sum::<usize>(), 100);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_reduce_static_stats() -> Result<()> {
+        let job = MapReduce::new(|x: u64| Ok(x)).num_threads(2);
+        let (_, stats) =
+            job.run_with_stats((0..10).map(|i| (i as usize, i)), 0, |acc, _, v| *acc += v)?;
+        assert_eq!(stats.tasks, vec![5, 5]);
+        assert_eq!(stats.steals, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_reduce_worker_panic_is_an_error() {
+        for scheduler in [Scheduler::Static, Scheduler::WorkStealing] {
+            let job = MapReduce::new(|x: i32| {
+                assert!(x != 3, "bad input {}", x);
+                Ok(x)
+            })
+            .scheduler(scheduler);
+            let ret = job.run((0..10).map(|i| (i as usize, i)), 0, |acc, _, v| *acc += v);
+            assert!(ret.is_err(), "{:?}", scheduler);
+        }
+    }
+
+    #[test]
+    fn test_map_reduce_map_error() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let job = MapReduce::new(|x: i32| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            if x == 3 {
+                Err(anyhow!("bad input {}", x))
+            } else {
+                Ok(x)
+            }
+        });
+        let ret = job.run((0..1000).map(|i| (i as usize, i)), 0, |acc, _, v| *acc += v);
+        assert!(ret.is_err());
+        // run 返回时 worker 都已经退出, 之后不会再有 map 调用
+        let calls = CALLS.load(Ordering::Relaxed);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(CALLS.load(Ordering::Relaxed), calls);
+    }
+
+    #[test]
+    fn test_map_reduce_map_error_stops_stealers() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let job = MapReduce::new(|x: i32| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            Err::<i32, _>(anyhow!("bad input {}", x))
+        })
+        .scheduler(Scheduler::WorkStealing);
+        let ret = job.run((0..1000).map(|i| (i as usize, i)), 0, |acc, _, v| *acc += v);
+        assert!(ret.is_err());
+        // 第一个错误之后每个线程最多再算手上的一个
+        assert!(CALLS.load(Ordering::Relaxed) < 100);
+    }
+}
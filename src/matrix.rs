@@ -1,57 +1,393 @@
 use std::{
     fmt::{self, Debug, Display, Formatter},
     ops::{Add, AddAssign, Mul},
-    sync::mpsc,
-    thread,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
 };
 
 use anyhow::Result;
+use rand::{distributions::Distribution, rngs::StdRng, Rng, SeedableRng};
 
-use crate::{dot_product, Vector};
+use crate::{dot, simd, MapReduce, Scheduler, SchedulerStats, Vector, VectorView};
+
+mod stats;
 
 const NUM_THREADS: usize = 4;
 
 // [[1,2], [3,4], [5,6]] -> [1, 2, 3, 4, 5, 6]
 // 后面这种方式效率更高
 
+#[derive(Clone)]
 pub struct Matrix<T> {
     data: Vec<T>,
     row: usize,
     col: usize,
+    layout: Layout,
+}
+
+/// The worker pool behind `multiply` and `multiply_batch`
+///
+/// The element type must be `Sync`: the workers share both operands behind an
+/// `Arc` instead of copying a row and a column into every task.
+#[derive(Debug, Clone, Copy)]
+pub struct MatrixPool {
+    num_threads: usize,
+    scheduler: Scheduler,
 }
 
+/// Storage order of `Matrix::data`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// `data[i * col + j]`
+    #[default]
+    RowMajor,
+    /// `data[j * row + i]`, as produced by Fortran/BLAS
+    ColMajor,
+}
+
+// 旧的 worker 消息类型, multiply 改用 MapReduce 之后不再使用, 保留给外部代码兼容
+#[deprecated(note = "multiply runs on MapReduce now and no longer sends Msg")]
+#[allow(dead_code)]
 pub struct MsgInput<T> {
     idx: usize,
     row: Vector<T>,
     col: Vector<T>,
 }
 
+#[deprecated(note = "multiply runs on MapReduce now and no longer sends Msg")]
+#[allow(dead_code)]
 pub struct MsgOutput<T> {
     idx: usize,
     value: T,
 }
 
+#[deprecated(note = "multiply runs on MapReduce now and no longer sends Msg")]
+#[allow(deprecated, dead_code)]
 pub struct Msg<T> {
     input: MsgInput<T>,
     /// sender to send the result back
     sender: oneshot::Sender<MsgOutput<T>>,
 }
 
+// 结果矩阵的一个元素: a 的第 i 行乘 b 的第 j 列, 两个操作数共享一份
+struct CellInput<T> {
+    idx: usize,
+    pair: Arc<(Matrix<T>, Matrix<T>)>,
+    i: usize,
+    j: usize,
+}
+
 // region:    --- impls
 impl<T: Debug> Matrix<T> {
     // 任何数据结构, 只要可以 convert 成 Vec<T>, 那么下面的代码就是可以通过的
     pub fn new(data: impl Into<Vec<T>>, row: usize, col: usize) -> Self {
+        Self::with_layout(data, row, col, Layout::RowMajor)
+    }
+}
+
+impl<T> Matrix<T> {
+    pub fn with_layout(data: impl Into<Vec<T>>, row: usize, col: usize, layout: Layout) -> Self {
         Self {
             data: data.into(),
             row,
             col,
+            layout,
+        }
+    }
+
+    /// A `row x col` matrix with every element sampled from `distribution`
+    pub fn random(row: usize, col: usize, distribution: impl Distribution<T>) -> Self {
+        Self::random_with_rng(row, col, distribution, &mut rand::thread_rng())
+    }
+
+    /// Like `random`, but the same seed always gives the same matrix
+    pub fn random_seeded(
+        row: usize,
+        col: usize,
+        distribution: impl Distribution<T>,
+        seed: u64,
+    ) -> Self {
+        Self::random_with_rng(row, col, distribution, &mut StdRng::seed_from_u64(seed))
+    }
+
+    pub fn random_with_rng<R: Rng + ?Sized>(
+        row: usize,
+        col: usize,
+        distribution: impl Distribution<T>,
+        rng: &mut R,
+    ) -> Self {
+        let data = distribution
+            .sample_iter(rng)
+            .take(row * col)
+            .collect::<Vec<_>>();
+        Self::with_layout(data, row, col, Layout::RowMajor)
+    }
+
+    /// Build a matrix from column-major data
+    pub fn col_major(data: impl Into<Vec<T>>, row: usize, col: usize) -> Self {
+        Self::with_layout(data, row, col, Layout::ColMajor)
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The raw storage, ordered according to `layout()`
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> Option<&T> {
+        if i >= self.row || j >= self.col {
+            return None;
+        }
+        self.data.get(self.offset(i, j))
+    }
+
+    /// Transpose without copying: a row-major m x n matrix is the same
+    /// buffer as a column-major n x m matrix
+    pub fn transpose(self) -> Self {
+        Self {
+            data: self.data,
+            row: self.col,
+            col: self.row,
+            layout: self.layout.flip(),
+        }
+    }
+
+    /// Copy the matrix into the given layout
+    pub fn to_layout(&self, layout: Layout) -> Self
+    where
+        T: Copy,
+    {
+        let data: Vec<T> = match layout {
+            Layout::RowMajor => (0..self.row)
+                .flat_map(|i| (0..self.col).map(move |j| self.data[self.offset(i, j)]))
+                .collect(),
+            Layout::ColMajor => (0..self.col)
+                .flat_map(|j| (0..self.row).map(move |i| self.data[self.offset(i, j)]))
+                .collect(),
+        };
+        Self::with_layout(data, self.row, self.col, layout)
+    }
+
+    fn offset(&self, i: usize, j: usize) -> usize {
+        match self.layout {
+            Layout::RowMajor => i * self.col + j,
+            Layout::ColMajor => j * self.row + i,
+        }
+    }
+
+    /// Row `i` without copying, strided for column-major storage
+    pub fn row_view(&self, i: usize) -> VectorView<'_, T> {
+        match self.layout {
+            Layout::RowMajor => VectorView::new(&self.data[i * self.col..(i + 1) * self.col]),
+            Layout::ColMajor => VectorView::strided(&self.data[i..], self.col, self.row)
+                .expect("Matrix row out of range"),
+        }
+    }
+
+    /// Column `j` without copying, strided for row-major storage
+    pub fn col_view(&self, j: usize) -> VectorView<'_, T> {
+        match self.layout {
+            Layout::RowMajor => VectorView::strided(&self.data[j..], self.row, self.col)
+                .expect("Matrix column out of range"),
+            Layout::ColMajor => VectorView::new(&self.data[j * self.row..(j + 1) * self.row]),
+        }
+    }
+
+    pub(crate) fn row_vector(&self, i: usize) -> Vector<T>
+    where
+        T: Copy,
+    {
+        self.row_view(i).to_vector()
+    }
+
+    pub(crate) fn col_vector(&self, j: usize) -> Vector<T>
+    where
+        T: Copy,
+    {
+        self.col_view(j).to_vector()
+    }
+}
+
+impl Default for MatrixPool {
+    fn default() -> Self {
+        Self::new(NUM_THREADS)
+    }
+}
+
+impl MatrixPool {
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            num_threads: num_threads.max(1),
+            scheduler: Scheduler::Static,
+        }
+    }
+
+    /// Use work stealing when tasks have uneven cost
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Every output cell is one task; before scheduling, each operand is
+    /// copied once into the layout that makes rows of `a` and columns of `b`
+    /// contiguous (a row-major `a` and column-major `b` need no transposing)
+    pub fn multiply<T>(&self, a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+    where
+        T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+    {
+        Ok(self.multiply_with_stats(a, b)?.0)
+    }
+
+    pub fn multiply_with_stats<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+    ) -> Result<(Matrix<T>, SchedulerStats)>
+    where
+        T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+    {
+        if a.col != b.row {
+            return Err(anyhow::anyhow!("Matrix multiply error: a.col != b.row"));
+        }
+        // region:    --- old code
+        // 不能放具体的类型, 因为 T 是泛型, 因此这里需要用 T::default()
+        // let mut data = vec![T::default(); a.row * b.col];
+        // for i in 0..a.row {
+        //     for j in 0..b.col {
+        //         for k in 0..a.col {
+        //             // data[i][j] += a[i][k] * b[k][j]
+        //             data[i * b.col + j] += a.data[i * a.col + k] * b.data[k * b.col + j];
+        //         }
+        //     }
+        // }
+        // endregion: --- old code
+
+        // region:    --- change to multithreading
+        // multiply 是 MapReduce 的一个使用者: map 阶段计算点积, reduce 阶段按 idx 写回
+        let (data, stats) = dot_product_job(self).run_with_stats(
+            map_inputs(a, b, 0),
+            vec![T::default(); a.row * b.col],
+            |data, idx, value| data[idx] = value,
+        )?;
+        // endregion: --- change to multithreading
+
+        let c = Matrix::with_layout(data, a.row, b.col, Layout::of_product(a.layout, b.layout));
+        Ok((c, stats))
+    }
+
+    pub fn multiply_batch<T>(&self, pairs: &[(Matrix<T>, Matrix<T>)]) -> Vec<Result<Matrix<T>>>
+    where
+        T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+    {
+        self.multiply_batch_with_stats(pairs).0
+    }
+
+    pub fn multiply_batch_with_stats<T>(
+        &self,
+        pairs: &[(Matrix<T>, Matrix<T>)],
+    ) -> (Vec<Result<Matrix<T>>>, SchedulerStats)
+    where
+        T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+    {
+        // every valid pair owns the range [offset, offset + a.row * b.col) of the output
+        let mut len = 0;
+        let offsets = pairs
+            .iter()
+            .map(|(a, b)| {
+                if a.col != b.row {
+                    return Err(anyhow::anyhow!("Matrix multiply error: a.col != b.row"));
+                }
+                let offset = len;
+                len += a.row * b.col;
+                Ok(offset)
+            })
+            .collect::<Vec<_>>();
+
+        // 每个 pair 输出区间的起点, reduce 时用 idx 找回它属于哪个 pair;
+        // 空的 pair 和下一个 pair 起点相同, 但它没有任务, 不会被找到
+        let starts = offsets
+            .iter()
+            .enumerate()
+            .filter_map(|(p, offset)| Some((*offset.as_ref().ok()?, p)))
+            .collect::<Vec<_>>();
+        let pair_of = |idx: usize| starts[starts.partition_point(|(start, _)| *start <= idx) - 1].1;
+
+        let inputs = pairs
+            .iter()
+            .zip(&offsets)
+            .filter_map(|((a, b), offset)| Some(map_inputs(a, b, *offset.as_ref().ok()?)))
+            .flatten();
+        let ret = isolated_dot_product_job(self).run_with_stats(
+            inputs,
+            (
+                vec![T::default(); len],
+                pairs.iter().map(|_| None).collect::<Vec<_>>(),
+            ),
+            |(data, errors), idx, value| match value {
+                Ok(value) => data[idx] = value,
+                Err(e) => {
+                    errors[pair_of(idx)].get_or_insert(e);
+                }
+            },
+        );
+        let (data, mut errors, stats) = match ret {
+            Ok(((data, errors), stats)) => (Ok(data), errors, stats),
+            Err(e) => (Err(e), Vec::new(), SchedulerStats::default()),
+        };
+
+        let results = pairs
+            .iter()
+            .zip(offsets)
+            .enumerate()
+            .map(|(p, ((a, b), offset))| {
+                let offset = offset?;
+                let data = data.as_ref().map_err(|e| anyhow::anyhow!("{:#}", e))?;
+                if let Some(e) = errors.get_mut(p).and_then(Option::take) {
+                    return Err(e);
+                }
+                Ok(Matrix::with_layout(
+                    &data[offset..offset + a.row * b.col],
+                    a.row,
+                    b.col,
+                    Layout::of_product(a.layout, b.layout),
+                ))
+            })
+            .collect();
+        (results, stats)
+    }
+}
+
+impl Layout {
+    pub fn flip(self) -> Self {
+        match self {
+            Layout::RowMajor => Layout::ColMajor,
+            Layout::ColMajor => Layout::RowMajor,
+        }
+    }
+
+    // 两个操作数都是列主序时, 结果也用列主序, 其余情况用行主序
+    fn of_product(a: Layout, b: Layout) -> Self {
+        match (a, b) {
+            (Layout::ColMajor, Layout::ColMajor) => Layout::ColMajor,
+            _ => Layout::RowMajor,
         }
     }
 }
 
 impl<T> Mul for Matrix<T>
 where
-    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + 'static,
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
 {
     type Output = Self;
 
@@ -69,7 +405,7 @@ where
         write!(f, "{{")?;
         for i in 0..self.row {
             for j in 0..self.col {
-                write!(f, "{}", self.data[i * self.col + j])?;
+                write!(f, "{}", self.data[self.offset(i, j)])?;
                 if j != self.col - 1 {
                     write!(f, " ")?;
                 }
@@ -84,6 +420,15 @@ where
     }
 }
 
+// 比较的是逻辑上的元素, 与存储顺序无关
+impl<T: PartialEq> PartialEq for Matrix<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.row == other.row
+            && self.col == other.col
+            && (0..self.row).all(|i| (0..self.col).all(|j| self.get(i, j) == other.get(i, j)))
+    }
+}
+
 impl<T> fmt::Debug for Matrix<T>
 where
     T: fmt::Display,
@@ -93,102 +438,235 @@ where
     }
 }
 
+#[allow(deprecated)]
 impl<T> MsgInput<T> {
     pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
         Self { idx, row, col }
     }
 }
 
+#[allow(deprecated)]
 impl<T> MsgOutput<T> {
     pub fn new(idx: usize, value: T) -> Self {
         Self { idx, value }
     }
 }
 
+#[allow(deprecated)]
 impl<T> Msg<T> {
     pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<T>>) -> Self {
         Self { input, sender }
     }
 }
 
+impl<T> CellInput<T> {
+    fn new(idx: usize, pair: Arc<(Matrix<T>, Matrix<T>)>, i: usize, j: usize) -> Self {
+        Self { idx, pair, i, j }
+    }
+}
+
 // endregion: --- impls
 
 // region:    --- functions
 // AB -> a.col == b.row (左乘)
 // 最后的矩阵是一个 a.row * b.col 的矩阵
+/// Multiply on a default `MatrixPool`; `T: Sync` for the reason given there
 pub fn multiply<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
 where
-    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + 'static,
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+{
+    MatrixPool::default().multiply(a, b)
+}
+
+/// Same as `multiply`, with an explicit number of worker threads
+pub fn multiply_with_threads<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    num_threads: usize,
+) -> Result<Matrix<T>>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+{
+    MatrixPool::new(num_threads).multiply(a, b)
+}
+
+/// Single-threaded multiply, the reference for the parallel version
+///
+/// The loop order follows the operand layouts so the innermost loop always
+/// walks contiguous memory. Like `multiply`, the result is column-major only
+/// when both operands are.
+pub fn multiply_seq<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T>,
 {
     if a.col != b.row {
         return Err(anyhow::anyhow!("Matrix multiply error: a.col != b.row"));
     }
-    // region:    --- old code
-    // 不能放具体的类型, 因为 T 是泛型, 因此这里需要用 T::default()
-    // let mut data = vec![T::default(); a.row * b.col];
-    // for i in 0..a.row {
-    //     for j in 0..b.col {
-    //         for k in 0..a.col {
-    //             // data[i][j] += a[i][k] * b[k][j]
-    //             data[i * b.col + j] += a.data[i * a.col + k] * b.data[k * b.col + j];
-    //         }
-    //     }
-    // }
-    // endregion: --- old code
-
-    // region:    --- change to multithreading
-    let senders = (0..NUM_THREADS)
-        .map(|_| {
-            let (tx, rx) = mpsc::channel::<Msg<T>>(); // 一个 Msg<T> 的 channel
-            thread::spawn(move || {
-                for msg in rx {
-                    let value = dot_product(msg.input.row, msg.input.col)?;
-                    if let Err(e) = msg.sender.send(MsgOutput::new(msg.input.idx, value)) {
-                        eprintln!("Send error: {:?}", e);
+    let (m, n, p) = (a.row, a.col, b.col);
+    let mut data = vec![T::default(); m * p];
+    match (a.layout, b.layout) {
+        // i-k-j: stream rows of b into rows of c
+        (Layout::RowMajor, Layout::RowMajor) => {
+            for i in 0..m {
+                for k in 0..n {
+                    let aik = a.data[i * n + k];
+                    for j in 0..p {
+                        data[i * p + j] += aik * b.data[k * p + j];
+                    }
+                }
+            }
+        }
+        // i-j-k: rows of a and columns of b are both contiguous
+        (Layout::RowMajor, Layout::ColMajor) => {
+            for i in 0..m {
+                for j in 0..p {
+                    let mut sum = T::default();
+                    for k in 0..n {
+                        sum += a.data[i * n + k] * b.data[j * n + k];
+                    }
+                    data[i * p + j] = sum;
+                }
+            }
+        }
+        // k-i-j: outer product of column k of a and row k of b
+        (Layout::ColMajor, Layout::RowMajor) => {
+            for k in 0..n {
+                for i in 0..m {
+                    let aik = a.data[k * m + i];
+                    for j in 0..p {
+                        data[i * p + j] += aik * b.data[k * p + j];
+                    }
+                }
+            }
+        }
+        // j-k-i: stream columns of a into columns of c
+        (Layout::ColMajor, Layout::ColMajor) => {
+            for j in 0..p {
+                for k in 0..n {
+                    let bkj = b.data[j * n + k];
+                    for i in 0..m {
+                        data[j * m + i] += a.data[k * m + i] * bkj;
                     }
                 }
-                Ok::<_, anyhow::Error>(())
-            });
-            tx
-        })
-        .collect::<Vec<_>>();
-    let matrix_len = a.row * b.col;
-    let mut data = vec![T::default(); matrix_len];
-    let mut receivers = Vec::with_capacity(matrix_len);
-
-    // map/reduce: map phase
-    for i in 0..a.row {
-        for j in 0..b.col {
-            let row = Vector::new(&a.data[i * a.col..(i + 1) * a.col]); // a[i][k]
-            let col_data = b.data[j..]
-                .iter()
-                .step_by(b.col)
-                .copied()
-                .collect::<Vec<_>>();
-            let col = Vector::new(col_data); // b[k][j]
-            let idx = i * b.col + j; // i,j -> idx
-            let input = MsgInput::new(idx, row, col);
-            let (tx, rx) = oneshot::channel();
-            let msg = Msg::new(input, tx);
-            if let Err(e) = senders[idx % NUM_THREADS].send(msg) {
-                eprintln!("Send error: {:?}", e);
             }
-            receivers.push(rx);
         }
     }
+    Ok(Matrix::with_layout(
+        data,
+        m,
+        p,
+        Layout::of_product(a.layout, b.layout),
+    ))
+}
+
+/// Multiply many independent matrix pairs on one shared worker pool
+///
+/// All pairs are scheduled at once, so a batch of small matrices doesn't pay
+/// for spinning up a new pool per pair. Each pair reports its own error,
+/// including a panic while computing one of its cells; only a failure of the
+/// pool itself (a worker thread dying) fails every pair.
+pub fn multiply_batch<T>(pairs: &[(Matrix<T>, Matrix<T>)]) -> Vec<Result<Matrix<T>>>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+{
+    MatrixPool::default().multiply_batch(pairs)
+}
 
-    // map/reduce: reduce phase
-    for rx in receivers {
-        let output = rx.recv()?;
-        data[output.idx] = output.value;
+/// Multiply a row panel by a column panel into a row-major tile
+///
+/// `a` holds `rows` rows of length `inner` back to back, `b` holds `cols`
+/// columns of length `inner` back to back.
+pub(crate) fn multiply_tile<T>(a: &[T], b: &[T], rows: usize, cols: usize, inner: usize) -> Vec<T>
+where
+    T: Copy + Default + AddAssign + Mul<Output = T> + 'static,
+{
+    if inner == 0 {
+        return vec![T::default(); rows * cols];
+    }
+    let mut data = Vec::with_capacity(rows * cols);
+    for row in a.chunks_exact(inner).take(rows) {
+        for col in b.chunks_exact(inner).take(cols) {
+            data.push(simd::dot(row, col));
+        }
     }
-    // endregion: --- change to multithreading
+    data
+}
 
-    Ok(Matrix {
-        data,
-        row: a.row,
-        col: b.col,
+fn dot_product_job<T>(pool: &MatrixPool) -> MapReduce<CellInput<T>, T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+{
+    MapReduce::new(|input: CellInput<T>| {
+        let (a, b) = &*input.pair;
+        dot(&a.row_view(input.i), &b.col_view(input.j))
     })
+    .num_threads(pool.num_threads)
+    .scheduler(pool.scheduler)
+}
+
+// multiply_batch 用: 一个元素的错误或 panic 作为输出交给 reduce, 只让它所在的 pair 失败
+fn isolated_dot_product_job<T>(pool: &MatrixPool) -> MapReduce<CellInput<T>, Result<T>>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync + 'static,
+{
+    MapReduce::new(|input: CellInput<T>| {
+        let (a, b) = &*input.pair;
+        let dot = panic::catch_unwind(AssertUnwindSafe(|| {
+            dot(&a.row_view(input.i), &b.col_view(input.j))
+        }));
+        Ok(dot.unwrap_or_else(|_| {
+            Err(anyhow::anyhow!(
+                "Matrix multiply error: dot product panicked"
+            ))
+        }))
+    })
+    .num_threads(pool.num_threads)
+    .scheduler(pool.scheduler)
+}
+
+// map/reduce: map phase, one dot product per output cell
+// 按结果矩阵的存储顺序生成任务; 两个矩阵各拷贝一次放进 Arc, 任务里按行/列 view 读取
+fn map_inputs<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    offset: usize,
+) -> impl Iterator<Item = (usize, CellInput<T>)>
+where
+    T: Copy,
+{
+    let layout = Layout::of_product(a.layout, b.layout);
+    let (outer, inner) = match layout {
+        Layout::RowMajor => (a.row, b.col),
+        Layout::ColMajor => (b.col, a.row),
+    };
+    let pair = Arc::new(pack_operands(a, b));
+    (0..outer).flat_map(move |x| {
+        let pair = Arc::clone(&pair);
+        (0..inner).map(move |y| {
+            let (i, j) = match layout {
+                Layout::RowMajor => (x, y),
+                Layout::ColMajor => (y, x),
+            };
+            let input = CellInput::new(offset + x * inner + y, Arc::clone(&pair), i, j); // i,j -> idx
+            (input.idx, input)
+        })
+    })
+}
+
+// 按两个操作数的存储顺序决定怎么拷贝: 拷贝之后 a 是行主序, b 是列主序,
+// 每个任务读的 a 的一行和 b 的一列都是连续内存, 点积可以走 SIMD
+fn pack_operands<T: Copy>(a: &Matrix<T>, b: &Matrix<T>) -> (Matrix<T>, Matrix<T>) {
+    match (a.layout, b.layout) {
+        // 行 x 列: 本来就是连续的, 直接复制
+        (Layout::RowMajor, Layout::ColMajor) => (a.clone(), b.clone()),
+        // b 的列是跨步的
+        (Layout::RowMajor, Layout::RowMajor) => (a.clone(), b.to_layout(Layout::ColMajor)),
+        // a 的行是跨步的
+        (Layout::ColMajor, Layout::ColMajor) => (a.to_layout(Layout::RowMajor), b.clone()),
+        (Layout::ColMajor, Layout::RowMajor) => {
+            (a.to_layout(Layout::RowMajor), b.to_layout(Layout::ColMajor))
+        }
+    }
 }
 
 // endregion: --- functions
@@ -233,6 +711,158 @@ mod tests {
         assert!(c.is_err());
     }
 
+    #[test]
+    fn test_multiply_batch() -> Result<()> {
+        let pairs = vec![
+            (
+                Matrix::new([1, 2, 3, 4, 5, 6], 2, 3),
+                Matrix::new([10, 11, 20, 21, 30, 31], 3, 2),
+            ),
+            (
+                Matrix::new([1, 2, 3, 4, 5, 6], 2, 3),
+                Matrix::new([1, 2, 3, 4], 2, 2),
+            ),
+            (
+                Matrix::new([1, 2, 3, 4], 2, 2),
+                Matrix::new([1, 2, 3, 4], 2, 2),
+            ),
+        ];
+        let results = multiply_batch(&pairs);
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().data, vec![140, 146, 320, 335]);
+        assert!(results[1].is_err());
+        assert_eq!(results[2].as_ref().unwrap().data, vec![7, 10, 15, 22]);
+        Ok(())
+    }
+
+    // 乘以负数时 panic, 用来模拟某一对矩阵在计算中失败
+    #[derive(Debug, Default, Clone, Copy, PartialEq)]
+    struct Fragile(i32);
+
+    impl Add for Fragile {
+        type Output = Self;
+        fn add(self, rhs: Self) -> Self {
+            Fragile(self.0 + rhs.0)
+        }
+    }
+
+    impl AddAssign for Fragile {
+        fn add_assign(&mut self, rhs: Self) {
+            self.0 += rhs.0;
+        }
+    }
+
+    impl Mul for Fragile {
+        type Output = Self;
+        fn mul(self, rhs: Self) -> Self {
+            assert!(rhs.0 >= 0, "negative operand");
+            Fragile(self.0 * rhs.0)
+        }
+    }
+
+    #[test]
+    fn test_multiply_batch_isolates_failures() {
+        let m = |data: [i32; 4]| Matrix::new(data.map(Fragile), 2, 2);
+        let pairs = vec![
+            (m([1, 2, 3, 4]), m([1, 2, 3, 4])),
+            (m([1, 2, 3, 4]), m([1, -2, 3, 4])),
+            (m([1, 0, 0, 1]), m([5, 6, 7, 8])),
+        ];
+        for scheduler in [Scheduler::Static, Scheduler::WorkStealing] {
+            let results = MatrixPool::new(2)
+                .scheduler(scheduler)
+                .multiply_batch(&pairs);
+            assert_eq!(
+                results[0].as_ref().unwrap().data,
+                [7, 10, 15, 22].map(Fragile)
+            );
+            assert!(results[1].is_err());
+            assert_eq!(results[2].as_ref().unwrap().data, [5, 6, 7, 8].map(Fragile));
+        }
+    }
+
+    #[test]
+    fn test_matrix_transpose_is_layout_swap() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let t = a.transpose();
+        assert_eq!(t.layout(), Layout::ColMajor);
+        assert_eq!((t.row(), t.col()), (3, 2));
+        assert_eq!(t.data, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(format!("{}", t), "{1 4, 2 5, 3 6}");
+        assert_eq!(t.to_layout(Layout::RowMajor).data, vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_matrix_row_and_col_views() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let row = |m: &Matrix<i32>, i| m.row_view(i).iter().copied().collect::<Vec<_>>();
+        let col = |m: &Matrix<i32>, j| m.col_view(j).iter().copied().collect::<Vec<_>>();
+        assert_eq!(row(&a, 1), vec![4, 5, 6]);
+        assert_eq!(col(&a, 2), vec![3, 6]);
+        assert_eq!(a.col_view(1).stride(), 3);
+
+        let c = a.to_layout(Layout::ColMajor);
+        assert_eq!(row(&c, 1), vec![4, 5, 6]);
+        assert_eq!(col(&c, 2), vec![3, 6]);
+        assert!(c.col_view(0).as_slice().is_some());
+    }
+
+    #[test]
+    fn test_matrix_multiply_all_layouts() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([10, 11, 20, 21, 30, 31], 3, 2);
+        let expected = Matrix::new([140, 146, 320, 335], 2, 2);
+        for la in [Layout::RowMajor, Layout::ColMajor] {
+            for lb in [Layout::RowMajor, Layout::ColMajor] {
+                let a = a.to_layout(la);
+                let b = b.to_layout(lb);
+                let c = multiply(&a, &b)?;
+                let c_seq = multiply_seq(&a, &b)?;
+                assert_eq!(c, expected);
+                assert_eq!(c_seq, expected);
+                assert_eq!(c.data, c_seq.data);
+                assert_eq!(c.layout(), Layout::of_product(la, lb));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_inputs_pack_per_layout() {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        let b = Matrix::new([10, 11, 20, 21, 30, 31], 3, 2);
+        for la in [Layout::RowMajor, Layout::ColMajor] {
+            for lb in [Layout::RowMajor, Layout::ColMajor] {
+                let (a, b) = (a.to_layout(la), b.to_layout(lb));
+                let (_, input) = map_inputs(&a, &b, 0).next().unwrap();
+                let (a, b) = &*input.pair;
+                assert!(a.row_view(1).as_slice().is_some());
+                assert!(b.col_view(1).as_slice().is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn test_matrix_pool_work_stealing() -> Result<()> {
+        let pairs = vec![
+            (
+                Matrix::new([1, 2, 3, 4], 2, 2),
+                Matrix::new([1, 2, 3, 4], 2, 2),
+            ),
+            (
+                Matrix::new([1, 2, 3, 4, 5, 6], 2, 3),
+                Matrix::new([10, 11, 20, 21, 30, 31], 3, 2),
+            ),
+        ];
+        let pool = MatrixPool::new(3).scheduler(Scheduler::WorkStealing);
+        let (results, stats) = pool.multiply_batch_with_stats(&pairs);
+        assert_eq!(results[0].as_ref().unwrap().data, vec![7, 10, 15, 22]);
+        assert_eq!(results[1].as_ref().unwrap().data, vec![140, 146, 320, 335]);
+        assert_eq!(stats.tasks.len(), 3);
+        assert_eq!(stats.tasks.iter().sum::<usize>(), 8);
+        Ok(())
+    }
+
     #[test]
     #[should_panic]
     fn test_a_can_not_multiply_b_panic() {
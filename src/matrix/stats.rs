@@ -0,0 +1,271 @@
+use std::ops::Add;
+
+use anyhow::Result;
+
+use super::{Layout, Matrix};
+use crate::{
+    parallel::{par_chunks, par_ranges, sum_of},
+    Float, Summation, Vector,
+};
+
+// 逐元素的操作与存储顺序无关, 直接按 data 切块并行;
+// 按行/列的操作按行号/列号切块并行
+
+// region:    --- element-wise
+impl<T> Matrix<T>
+where
+    T: Copy + Send + Sync,
+{
+    /// Apply `f` to every element, in parallel for large matrices
+    pub fn map<U, F>(&self, f: F) -> Matrix<U>
+    where
+        U: Send,
+        F: Fn(T) -> U + Sync,
+    {
+        let data = par_chunks(&self.data, |chunk| {
+            chunk.iter().map(|&x| f(x)).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        Matrix::with_layout(data, self.row, self.col, self.layout)
+    }
+
+    /// Combine two matrices of the same shape element by element
+    pub fn zip_with<U, F>(&self, other: &Matrix<T>, f: F) -> Result<Matrix<U>>
+    where
+        U: Send,
+        F: Fn(T, T) -> U + Sync,
+    {
+        if self.row != other.row || self.col != other.col {
+            return Err(anyhow::anyhow!("Matrix zip error: shapes differ"));
+        }
+        let converted;
+        let other = if other.layout == self.layout {
+            other
+        } else {
+            converted = other.to_layout(self.layout);
+            &converted
+        };
+        let data = par_ranges(self.data.len(), 1, |range| {
+            self.data[range.clone()]
+                .iter()
+                .zip(&other.data[range])
+                .map(|(&x, &y)| f(x, y))
+                .collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+        Ok(Matrix::with_layout(data, self.row, self.col, self.layout))
+    }
+}
+// endregion: --- element-wise
+
+// region:    --- reductions
+impl<T> Matrix<T>
+where
+    T: Copy + Default + Add<Output = T> + Send + Sync,
+{
+    pub fn sum(&self) -> T {
+        par_chunks(&self.data, |chunk| sum_of(chunk.iter().copied()))
+            .into_iter()
+            .fold(T::default(), |acc, x| acc + x)
+    }
+
+    /// Sum of every row, one entry per row
+    pub fn row_sums(&self) -> Vector<T> {
+        let sums = par_ranges(self.row, self.col, |rows| {
+            rows.map(|i| sum_of((0..self.col).map(|j| self.data[self.offset(i, j)])))
+                .collect::<Vec<_>>()
+        });
+        Vector::new(sums.concat())
+    }
+
+    /// Sum of every column, one entry per column
+    pub fn col_sums(&self) -> Vector<T> {
+        let sums = par_ranges(self.col, self.row, |cols| {
+            cols.map(|j| sum_of((0..self.row).map(|i| self.data[self.offset(i, j)])))
+                .collect::<Vec<_>>()
+        });
+        Vector::new(sums.concat())
+    }
+}
+
+impl<T> Matrix<T>
+where
+    T: Copy + PartialOrd + Send + Sync,
+{
+    pub fn min(&self) -> Option<T> {
+        self.extreme(|x, best| x < best)
+            .map(|(idx, _)| self.data[idx])
+    }
+
+    pub fn max(&self) -> Option<T> {
+        self.extreme(|x, best| x > best)
+            .map(|(idx, _)| self.data[idx])
+    }
+
+    /// Position `(i, j)` of the largest element, the first one on ties
+    pub fn argmax(&self) -> Option<(usize, usize)> {
+        self.extreme(|x, best| x > best).map(|(_, pos)| pos)
+    }
+
+    // 返回 (data 下标, (i, j)), 相等时取逻辑位置 (i, j) 更靠前的元素
+    fn extreme(&self, better: impl Fn(T, T) -> bool + Sync) -> Option<(usize, (usize, usize))> {
+        let pos = |idx: usize| match self.layout {
+            Layout::RowMajor => (idx / self.col, idx % self.col),
+            Layout::ColMajor => (idx % self.row, idx / self.row),
+        };
+        let pick = |best: Option<(usize, (usize, usize))>, idx: usize| match best {
+            Some((b, bpos)) => {
+                let (x, y) = (self.data[idx], self.data[b]);
+                if better(x, y) || (!better(y, x) && pos(idx) < bpos) {
+                    Some((idx, pos(idx)))
+                } else {
+                    Some((b, bpos))
+                }
+            }
+            None => Some((idx, pos(idx))),
+        };
+        par_ranges(self.data.len(), 1, |range| range.fold(None, pick))
+            .into_iter()
+            .flatten()
+            .fold(None, |best, (idx, _)| pick(best, idx))
+    }
+}
+
+impl<T: Float> Matrix<T> {
+    /// Mean of every column, an error if the matrix has no rows
+    pub fn col_mean(&self) -> Result<Vector<T>> {
+        if self.row == 0 {
+            return Err(anyhow::anyhow!("Matrix stats error: no rows to average"));
+        }
+        let n = T::from_usize(self.row);
+        Ok(Vector::new(
+            self.col_sums().iter().map(|&s| s / n).collect::<Vec<_>>(),
+        ))
+    }
+
+    /// Population variance of every column, computed in two passes; an error
+    /// if the matrix has no rows
+    pub fn col_variance(&self) -> Result<Vector<T>> {
+        let mean = self.col_mean()?;
+        let n = T::from_usize(self.row);
+        let vars = par_ranges(self.col, self.row, |cols| {
+            cols.map(|j| {
+                let sq = (0..self.row).map(|i| {
+                    let d = self.data[self.offset(i, j)] - mean[j];
+                    d * d
+                });
+                sum_of(sq) / n
+            })
+            .collect::<Vec<_>>()
+        });
+        Ok(Vector::new(vars.concat()))
+    }
+
+    /// Like `sum`, accumulating with the given `Summation`
+    ///
+    /// Each parallel chunk is summed on its own, then the partial sums are
+    /// combined with the same strategy.
+    pub fn sum_with(&self, summation: Summation) -> T {
+        let partials = par_chunks(&self.data, |chunk| summation.sum(chunk));
+        summation.sum(&partials)
+    }
+
+    pub fn row_sums_with(&self, summation: Summation) -> Vector<T> {
+        let sums = par_ranges(self.row, self.col, |rows| {
+            rows.map(|i| summation.sum_by(self.col, |j| self.data[self.offset(i, j)]))
+                .collect::<Vec<_>>()
+        });
+        Vector::new(sums.concat())
+    }
+
+    pub fn col_sums_with(&self, summation: Summation) -> Vector<T> {
+        let sums = par_ranges(self.col, self.row, |cols| {
+            cols.map(|j| summation.sum_by(self.row, |i| self.data[self.offset(i, j)]))
+                .collect::<Vec<_>>()
+        });
+        Vector::new(sums.concat())
+    }
+}
+// endregion: --- reductions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_map_and_zip_with() -> Result<()> {
+        let a = Matrix::new([1, 2, 3, 4, 5, 6], 2, 3);
+        assert_eq!(a.map(|x| x * 10).data, vec![10, 20, 30, 40, 50, 60]);
+
+        let b = Matrix::new([6, 5, 4, 3, 2, 1], 2, 3).to_layout(Layout::ColMajor);
+        let c = a.zip_with(&b, |x, y| x + y)?;
+        assert_eq!(c.data, vec![7; 6]);
+        let d = Matrix::new([1, 2, 3, 4, 5, 6], 3, 2);
+        assert!(a.zip_with(&d, |x, y| x + y).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_reductions() {
+        let a = Matrix::new([1, 9, 3, 4, 9, 6], 2, 3);
+        assert_eq!(a.sum(), 32);
+        assert_eq!(a.min(), Some(1));
+        assert_eq!(a.max(), Some(9));
+        assert_eq!(a.argmax(), Some((0, 1)));
+        assert_eq!(a.row_sums().into_vec(), vec![13, 19]);
+        assert_eq!(a.col_sums().into_vec(), vec![5, 18, 9]);
+
+        let t = a.to_layout(Layout::ColMajor);
+        assert_eq!(t.argmax(), Some((0, 1)));
+        assert_eq!(t.row_sums().into_vec(), vec![13, 19]);
+    }
+
+    #[test]
+    fn test_matrix_reductions_parallel_path() {
+        let (row, col) = (300, 200);
+        let data = (0..row * col)
+            .map(|x| (x % 1000) as i64)
+            .collect::<Vec<_>>();
+        let a = Matrix::new(data.clone(), row, col);
+        assert_eq!(a.sum(), data.iter().sum::<i64>());
+        assert_eq!(a.max(), Some(999));
+        assert_eq!(a.argmax(), Some((4, 199)));
+        let row_sums = a.row_sums();
+        assert_eq!(row_sums.len(), row);
+        assert_eq!(row_sums[0], data[..col].iter().sum::<i64>());
+        assert_eq!(a.map(|x| x * 2).sum(), a.sum() * 2);
+    }
+
+    #[test]
+    fn test_matrix_col_mean_and_variance() -> Result<()> {
+        let a = Matrix::new([1.0, 10.0, 2.0, 20.0, 3.0, 30.0, 4.0, 40.0], 4, 2);
+        assert_eq!(a.col_mean()?.into_vec(), vec![2.5, 25.0]);
+        assert_eq!(a.col_variance()?.into_vec(), vec![1.25, 125.0]);
+        let empty = Matrix::<f64>::new(vec![], 0, 2);
+        assert!(empty.col_mean().is_err());
+        assert!(empty.col_variance().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_sum_with_compensated() {
+        // 大数相消, 朴素累加会把 1.0 全部吃掉
+        let a = Matrix::new([1e16, 1.0, -1e16, 1.0, 1.0, 1.0], 3, 2);
+        assert_eq!(a.sum_with(Summation::Naive), 3.0);
+        assert_eq!(a.sum_with(Summation::Neumaier), 4.0);
+        assert_eq!(
+            a.col_sums_with(Summation::Neumaier).into_vec(),
+            vec![1.0, 3.0]
+        );
+
+        let big = Matrix::new(vec![0.1f32; 400 * 300], 400, 300);
+        let exact = 0.1f32 as f64 * (400 * 300) as f64;
+        let err = |s| (big.sum_with(s) as f64 - exact).abs() / exact;
+        assert!(err(Summation::Kahan) <= 2.0 * f32::EPSILON as f64);
+        assert!(err(Summation::Pairwise) < err(Summation::Naive));
+    }
+}
@@ -1,5 +1,128 @@
+use anyhow::Result;
+
 mod amap;
 mod cmap;
+mod exposition;
+mod kinds;
+mod labels;
+mod server;
+mod snapshot;
+mod striped;
+mod window;
 
 pub use amap::*;
 pub use cmap::*;
+pub use exposition::*;
+pub use kinds::*;
+pub use labels::LabelPairs;
+pub use server::*;
+pub use snapshot::*;
+pub use striped::*;
+pub use window::*;
+
+/// The operations shared by every metrics backend
+///
+/// Backends differ on unknown keys: `AmapMetrics` returns an error until the
+/// key is registered, `CmapMetrics` creates the key on first use. Every key has
+/// a `MetricKind`, an operation the kind doesn't support (`dec` on a counter)
+/// is an error.
+pub trait Metrics: Send + Sync {
+    fn inc(&self, key: &str) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    fn dec(&self, key: &str) -> Result<()> {
+        self.add(key, -1)
+    }
+
+    fn add(&self, key: &str, delta: i64) -> Result<()>;
+
+    /// Current value of a counter or gauge, `None` if the key doesn't exist
+    /// or holds a float gauge, histogram or meter
+    fn get(&self, key: &str) -> Option<i64>;
+
+    /// A sorted, timestamped copy of every key and its current value
+    fn snapshot(&self) -> MetricsSnapshot;
+
+    /// Set every value back to 0, keys stay registered
+    fn reset(&self);
+}
+
+/// Operations on the individual `MetricKind`s, for backends that support
+/// all of them
+///
+/// Kept apart from `Metrics` so a backend that only counts doesn't have to
+/// implement gauges, histograms and handles.
+pub trait TypedMetrics: Metrics {
+    /// Set a gauge
+    fn set(&self, key: &str, value: i64) -> Result<()>;
+
+    /// Set a float gauge
+    fn set_f64(&self, key: &str, value: f64) -> Result<()>;
+
+    /// Record one observation in a histogram
+    fn observe(&self, key: &str, value: f64) -> Result<()>;
+
+    /// A timer that records into the histogram `key`
+    fn timer(&self, key: &str) -> Result<Timer>;
+
+    /// A handle to the counter `key`
+    ///
+    /// The handle holds the atomic itself, so incrementing through it skips
+    /// the key lookup. It stays valid across `snapshot` and `reset`.
+    fn counter_handle(&self, key: &str) -> Result<Counter>;
+
+    /// A handle to the gauge `key`, see `counter_handle`
+    fn gauge_handle(&self, key: &str) -> Result<Gauge>;
+
+    /// A handle to the meter `key`, for 1/5/15-minute rates, see `counter_handle`
+    fn meter_handle(&self, key: &str) -> Result<Meter>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise(metrics: &dyn Metrics) -> Result<()> {
+        metrics.inc("a")?;
+        metrics.inc("a")?;
+        metrics.add("b", 10)?;
+        metrics.dec("b")?;
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("b"), Some(9));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot.iter().collect::<Vec<_>>(),
+            vec![("a", &MetricValue::Gauge(2)), ("b", &MetricValue::Gauge(9))]
+        );
+
+        metrics.reset();
+        assert_eq!(metrics.get("a"), Some(0));
+        assert_eq!(metrics.get("b"), Some(0));
+        assert_eq!(
+            metrics.snapshot().diff(&snapshot).deltas["b"],
+            MetricValue::Gauge(-9)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_backends_behind_dyn_metrics() -> Result<()> {
+        exercise(&AmapMetrics::new(&["a", "b"]))?;
+        exercise(&CmapMetrics::new())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let amap = AmapMetrics::new(&["a"]);
+        assert!(Metrics::inc(&amap, "missing").is_err());
+        assert_eq!(Metrics::get(&amap, "missing"), None);
+
+        let cmap = CmapMetrics::new();
+        assert!(Metrics::inc(&cmap, "new").is_ok());
+        assert_eq!(Metrics::get(&cmap, "new"), Some(1));
+        assert_eq!(Metrics::get(&cmap, "missing"), None);
+    }
+}
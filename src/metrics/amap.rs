@@ -1,50 +1,259 @@
 use anyhow::Result;
-use std::{
-    collections::HashMap,
-    fmt,
-    sync::{
-        atomic::{AtomicI64, Ordering},
-        Arc,
-    },
+use arc_swap::ArcSwap;
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use super::{
+    Clock, Counter, FloatGauge, Gauge, Histogram, Meter, Metrics, MetricsSnapshot, Slot,
+    SystemClock, Timer, TypedMetrics,
 };
 
 // Rust 标准库提供了一些原子类型，可以在多线程环境下安全地共享和修改数据
 // 不需要使用锁，原子类型的操作是无锁的，因此性能更好
+//
+// 运行时注册新指标时复制一份 HashMap 再整体替换 (copy-on-write),
+// 读的一方只是 ArcSwap::load, 仍然不加锁
 #[derive(Debug, Clone)]
 pub struct AmapMetrics {
-    data: Arc<HashMap<&'static str, AtomicI64>>,
+    data: Arc<ArcSwap<HashMap<Arc<str>, Slot>>>,
+    // meter 读时间用的时钟
+    clock: Arc<dyn Clock>,
+}
+
+/// Registers the metrics of an `AmapMetrics` and their kinds
+#[derive(Debug, Default)]
+pub struct AmapMetricsBuilder {
+    slots: Vec<(Arc<str>, Slot)>,
+    // meter 要等 build 时才知道用哪个时钟
+    meters: Vec<Arc<str>>,
+    clock: Option<Arc<dyn Clock>>,
+    error: Option<anyhow::Error>,
 }
 
 impl AmapMetrics {
-    pub fn new(metric_names: &[&'static str]) -> Self {
-        // 初始化 HashMap，每个 key 对应一个 AtomicI64 类型的值
+    /// Every name is registered as a gauge, so `inc` and `dec` both work
+    pub fn new(metric_names: &[&str]) -> Self {
+        // 初始化 HashMap，每个 key 对应一个原子变量
         let map = metric_names
             .iter()
-            .map(|&name| (name, AtomicI64::new(0)))
+            .map(|&name| (name.into(), Slot::Gauge(Gauge::new())))
             .collect();
+        Self::from_map(map, Arc::new(SystemClock))
+    }
+
+    fn from_map(map: HashMap<Arc<str>, Slot>, clock: Arc<dyn Clock>) -> Self {
         AmapMetrics {
-            data: Arc::new(map),
+            data: Arc::new(ArcSwap::from_pointee(map)),
+            clock,
         }
     }
 
+    pub fn builder() -> AmapMetricsBuilder {
+        AmapMetricsBuilder::default()
+    }
+
     pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
-        let key = key.as_ref();
-        let counter = self
-            .data
-            .get(key)
-            .ok_or_else(|| anyhow::anyhow!("key {} not found", key))?;
-        counter.fetch_add(1, Ordering::Relaxed);
-        Ok(())
+        Metrics::add(self, key.as_ref(), 1)
     }
 
     pub fn dec(&self, key: impl AsRef<str>) -> Result<()> {
-        let key = key.as_ref();
-        let counter = self
-            .data
+        Metrics::add(self, key.as_ref(), -1)
+    }
+
+    /// Register `name` as a counter after construction; fine if it already is one
+    ///
+    /// Registering copies the key table, so it costs O(keys). Clones of this
+    /// `AmapMetrics` see the new key, existing handles keep working.
+    pub fn register_counter(&self, name: &str) -> Result<()> {
+        self.register(name, Slot::Counter(Counter::new()))
+    }
+
+    pub fn register_striped_counter(&self, name: &str) -> Result<()> {
+        self.register(name, Slot::Counter(Counter::striped()))
+    }
+
+    pub fn register_gauge(&self, name: &str) -> Result<()> {
+        self.register(name, Slot::Gauge(Gauge::new()))
+    }
+
+    pub fn register_float_gauge(&self, name: &str) -> Result<()> {
+        self.register(name, Slot::FloatGauge(FloatGauge::new()))
+    }
+
+    pub fn register_histogram(&self, name: &str, bounds: &[f64]) -> Result<()> {
+        self.register(name, Slot::Histogram(Histogram::new(bounds)?))
+    }
+
+    /// A meter reading this registry's clock, see `AmapMetricsBuilder::clock`
+    pub fn register_meter(&self, name: &str) -> Result<()> {
+        self.register(name, Slot::Meter(Meter::new(self.clock.clone())))
+    }
+
+    fn register(&self, name: &str, slot: Slot) -> Result<()> {
+        if let Some(existing) = self.data.load().get(name) {
+            return check_kind(name, existing, &slot);
+        }
+        let name: Arc<str> = name.into();
+        // rcu 在别的线程同时注册时会重试, 所以闭包里每次都从最新的 map 复制
+        let previous = self.data.rcu(|map| {
+            if map.contains_key(&name) {
+                return Arc::clone(map);
+            }
+            let mut map = HashMap::clone(map);
+            map.insert(name.clone(), slot.clone());
+            Arc::new(map)
+        });
+        match previous.get(&name) {
+            Some(existing) => check_kind(&name, existing, &slot),
+            None => Ok(()),
+        }
+    }
+
+    fn with_slot<R>(&self, key: &str, f: impl FnOnce(&Slot) -> Result<R>) -> Result<R> {
+        let data = self.data.load();
+        let slot = data
             .get(key)
             .ok_or_else(|| anyhow::anyhow!("key {} not found", key))?;
-        counter.fetch_sub(1, Ordering::Relaxed);
-        Ok(())
+        f(slot)
+    }
+}
+
+// 重复注册同一种类型没关系, 类型不同 (或者普通/分片 counter 不同) 报错
+fn check_kind(name: &str, existing: &Slot, slot: &Slot) -> Result<()> {
+    let striped = |s: &Slot| match s {
+        Slot::Counter(c) => c.is_striped(),
+        _ => false,
+    };
+    if existing.kind() != slot.kind() || striped(existing) != striped(slot) {
+        return Err(anyhow::anyhow!(
+            "AmapMetrics error: {} is already a {}{}",
+            name,
+            if striped(existing) { "striped " } else { "" },
+            existing.kind()
+        ));
+    }
+    Ok(())
+}
+
+impl AmapMetricsBuilder {
+    pub fn counter(self, name: &str) -> Self {
+        self.slot(name, Slot::Counter(Counter::new()))
+    }
+
+    /// A counter sharded across cache lines, see `StripedCounter`
+    pub fn striped_counter(self, name: &str) -> Self {
+        self.slot(name, Slot::Counter(Counter::striped()))
+    }
+
+    pub fn gauge(self, name: &str) -> Self {
+        self.slot(name, Slot::Gauge(Gauge::new()))
+    }
+
+    pub fn float_gauge(self, name: &str) -> Self {
+        self.slot(name, Slot::FloatGauge(FloatGauge::new()))
+    }
+
+    pub fn histogram(mut self, name: &str, bounds: &[f64]) -> Self {
+        match Histogram::new(bounds) {
+            Ok(h) => self.slot(name, Slot::Histogram(h)),
+            Err(e) => {
+                self.error.get_or_insert(e);
+                self
+            }
+        }
+    }
+
+    /// Counts events with 1/5/15-minute windowed and EWMA rates
+    pub fn meter(mut self, name: &str) -> Self {
+        self.meters.push(name.into());
+        self
+    }
+
+    /// The clock meters read, `SystemClock` by default
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Fails on duplicate names or invalid histogram bounds
+    pub fn build(self) -> Result<AmapMetrics> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let clock = self.clock.unwrap_or_else(|| Arc::new(SystemClock));
+        let meters = self
+            .meters
+            .into_iter()
+            .map(|name| (name, Slot::Meter(Meter::new(clock.clone()))));
+        let mut map = HashMap::with_capacity(self.slots.len());
+        for (name, slot) in self.slots.into_iter().chain(meters) {
+            if map.insert(name.clone(), slot).is_some() {
+                return Err(anyhow::anyhow!(
+                    "AmapMetrics error: {} registered twice",
+                    name
+                ));
+            }
+        }
+        Ok(AmapMetrics::from_map(map, clock))
+    }
+
+    fn slot(mut self, name: &str, slot: Slot) -> Self {
+        self.slots.push((name.into(), slot));
+        self
+    }
+}
+
+impl Metrics for AmapMetrics {
+    fn add(&self, key: &str, delta: i64) -> Result<()> {
+        self.with_slot(key, |s| s.add(key, delta))
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        self.data.load().get(key)?.get()
+    }
+
+    fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot::new(
+            self.data
+                .load()
+                .iter()
+                .map(|(key, slot)| (key.to_string(), slot.value())),
+        )
+    }
+
+    fn reset(&self) {
+        for slot in self.data.load().values() {
+            slot.reset();
+        }
+    }
+}
+
+impl TypedMetrics for AmapMetrics {
+    fn set(&self, key: &str, value: i64) -> Result<()> {
+        self.with_slot(key, |s| s.set(key, value))
+    }
+
+    fn set_f64(&self, key: &str, value: f64) -> Result<()> {
+        self.with_slot(key, |s| s.set_f64(key, value))
+    }
+
+    fn observe(&self, key: &str, value: f64) -> Result<()> {
+        self.with_slot(key, |s| s.observe(key, value))
+    }
+
+    fn timer(&self, key: &str) -> Result<Timer> {
+        self.with_slot(key, |s| s.timer(key))
+    }
+
+    fn counter_handle(&self, key: &str) -> Result<Counter> {
+        self.with_slot(key, |s| s.counter(key))
+    }
+
+    fn gauge_handle(&self, key: &str) -> Result<Gauge> {
+        self.with_slot(key, |s| s.gauge(key))
+    }
+
+    fn meter_handle(&self, key: &str) -> Result<Meter> {
+        self.with_slot(key, |s| s.meter(key))
     }
 }
 
@@ -58,9 +267,98 @@ impl AmapMetrics {
 
 impl fmt::Display for AmapMetrics {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for (key, value) in self.data.iter() {
-            writeln!(f, "{}: {}", key, value.load(Ordering::Relaxed))?;
+        for (key, slot) in self.data.load().iter() {
+            writeln!(f, "{}: {}", key, slot.value())?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ManualClock, MetricValue};
+
+    #[test]
+    fn test_amap_unregistered_keys() -> Result<()> {
+        let metrics = AmapMetrics::builder()
+            .counter("requests")
+            .meter("req")
+            .clock(Arc::new(ManualClock::new()))
+            .build()?;
+        // 只有注册过的 key 可以用, 不会按第一次的操作创建
+        assert!(metrics.inc("missing").is_err());
+        assert!(metrics.set("missing", 1).is_err());
+        assert!(metrics.observe("missing", 1.0).is_err());
+        assert!(metrics.timer("missing").is_err());
+        assert!(metrics.counter_handle("missing").is_err());
+        assert!(metrics.gauge_handle("missing").is_err());
+        assert!(metrics.meter_handle("missing").is_err());
+        assert_eq!(metrics.get("missing"), None);
+        assert!(metrics.gauge_handle("requests").is_err());
+        assert!(matches!(
+            metrics.snapshot().get("req"),
+            Some(MetricValue::Meter(m)) if m.count == 0
+        ));
+
+        // AmapMetrics::new 注册的是 gauge, 拿不到只增的 counter 句柄
+        let metrics = AmapMetrics::new(&["req"]);
+        assert!(metrics.counter_handle("req").is_err());
+        metrics.gauge_handle("req")?.inc();
+        assert_eq!(metrics.get("req"), Some(1));
+
+        assert!(AmapMetrics::builder()
+            .gauge("a")
+            .counter("a")
+            .build()
+            .is_err());
+        assert!(AmapMetrics::builder()
+            .histogram("h", &[2.0, 1.0])
+            .build()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_runtime_registration() -> Result<()> {
+        let metrics = AmapMetrics::new(&["a"]);
+        let clone = metrics.clone();
+        let a = metrics.gauge_handle("a")?;
+        assert!(metrics.inc("page.7").is_err());
+
+        let page = format!("page.{}", 7);
+        metrics.register_counter(&page)?;
+        metrics.register_counter(&page)?;
+        assert!(metrics.register_gauge(&page).is_err());
+        assert!(metrics.register_striped_counter(&page).is_err());
+        clone.inc("page.7")?;
+        a.inc();
+        assert_eq!(metrics.get("page.7"), Some(1));
+        assert_eq!(metrics.get("a"), Some(1));
+
+        // 多个线程同时注册, 每个 key 只会有一份
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let metrics = &metrics;
+                s.spawn(move || {
+                    for i in 0..50 {
+                        let key = format!("k{}", (i + t) % 60);
+                        metrics.register_counter(&key).unwrap();
+                        metrics.inc(&key).unwrap();
+                    }
+                });
+            }
+        });
+        let total: i64 = metrics
+            .snapshot()
+            .iter()
+            .filter(|(k, _)| k.starts_with('k'))
+            .filter_map(|(_, v)| v.as_i64())
+            .sum();
+        assert_eq!(total, 200);
+
+        metrics.register_meter("late")?;
+        assert!(metrics.register_counter("late").is_err());
+        Ok(())
+    }
+}
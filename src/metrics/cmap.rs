@@ -1,11 +1,34 @@
 // metrics data structure
 // basic functions: inc/dec/snapshot
 
-use std::{fmt::Display, sync::Arc};
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
 
 use anyhow::Result;
 use dashmap::DashMap;
 
+use super::{
+    labels::{Interner, MetricKey, SeriesLookup, SeriesRef},
+    Clock, Counter, FloatGauge, Gauge, Histogram, Meter, MetricValue, Metrics, MetricsSnapshot,
+    Slot, SystemClock, Timer, TypedMetrics,
+};
+
+/// Where new series go once a `CmapMetrics` is at its cardinality limit
+pub const OVERFLOW_KEY: &str = "__overflow__";
+/// Snapshot key counting the operations on new series that were rejected,
+/// redirected or had to evict a series, see `CmapMetrics::dropped_ops`
+pub const DROPPED_OPS: &str = "__dropped_ops__";
+
+const OVERFLOW_SERIES: SeriesRef<'static> = SeriesRef {
+    name: OVERFLOW_KEY,
+    labels: &[],
+};
+
 // region:    --- HashMap Version
 // // metrics table
 // #[derive(Debug, Default, Clone)]
@@ -82,10 +105,39 @@ use dashmap::DashMap;
 // endregion: --- HashMap Version
 
 // region:    --- DashMap Version
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct CmapMetrics {
-    // Arc<Mutex<HashMap<String, i64>>> => Arc<DashMap<String, i64>>
-    data: Arc<DashMap<String, i64>>, // 不需要加锁, 因为 DashMap 本身是线程安全的
+    // Arc<Mutex<HashMap<String, i64>>> => Arc<DashMap<MetricKey, Series>>
+    data: Arc<DashMap<MetricKey, Series>>, // 不需要加锁, 因为 DashMap 本身是线程安全的
+    // 标签名和值大量重复 (page="1" ...), 只保存一份
+    strings: Arc<Interner>,
+    limit: Option<(usize, OverflowPolicy)>,
+    dropped: Counter,
+    // 逻辑时钟, 每插入一个新 key 加一; EvictLru 时记录每个 series 最后一次更新的时刻
+    ticks: Arc<AtomicU64>,
+    // meter 读时间用的时钟
+    clock: Arc<dyn Clock>,
+    // 只有插入新 key 时才拿这把锁, 保证不会超过上限; 已有的 key 不受影响
+    inserting: Arc<Mutex<()>>,
+}
+
+/// What `CmapMetrics` does with a new series once it holds `limit` of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// return an error
+    #[default]
+    Reject,
+    /// record into the `__overflow__` series instead, which takes one of the
+    /// `limit` slots; it has the kind of the first operation that overflows,
+    /// later operations of another kind are only counted in `dropped_ops`
+    OverflowKey,
+    /// drop the least recently updated series to make room
+    EvictLru,
+}
+
+struct Series {
+    slot: Slot,
+    touched: AtomicU64,
 }
 
 // region:    --- impls
@@ -94,26 +146,482 @@ impl CmapMetrics {
         Self::default()
     }
 
+    /// Keep at most `limit` series, `policy` decides what happens to new ones
+    /// beyond that; each such operation counts in `dropped_ops`
+    ///
+    /// With `OverflowKey` the limit includes `__overflow__` itself, so at most
+    /// `limit - 1` other series are kept (and `__overflow__` alone for a limit of 0).
+    ///
+    /// With `EvictLru`, "recently updated" has the granularity of insertions
+    /// and only counts updates through `CmapMetrics`, not through handles. An
+    /// evicted series' handles keep working but are no longer exported.
+    pub fn max_cardinality(mut self, limit: usize, policy: OverflowPolicy) -> Self {
+        self.limit = Some((limit, policy));
+        self
+    }
+
+    /// The clock meters read, `SystemClock` by default; meters created
+    /// before the call keep the old one
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// How many operations on a new series were rejected, redirected to
+    /// `__overflow__` or evicted a series
+    ///
+    /// This counts operations, not distinct keys: a thousand `inc("x")` on a
+    /// full map with `Reject` count a thousand.
+    pub fn dropped_ops(&self) -> i64 {
+        self.dropped.get()
+    }
+
     pub fn inc(&self, key: impl Into<String>) -> Result<()> {
-        let mut counter = self.data.entry(key.into()).or_insert(0);
-        *counter += 1;
-        Ok(())
+        let key = key.into();
+        Metrics::add(self, &key, 1)
     }
 
     pub fn dec(&self, key: impl Into<String>) -> Result<()> {
-        let mut counter = self.data.entry(key.into()).or_insert(0);
-        *counter -= 1;
+        let key = key.into();
+        Metrics::add(self, &key, -1)
+    }
+
+    /// The counter `name` with `labels`, created on first use
+    ///
+    /// Label order doesn't matter, `[("a", "1"), ("b", "2")]` and
+    /// `[("b", "2"), ("a", "1")]` are the same series. Looking up an existing
+    /// series doesn't allocate.
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Result<Counter> {
+        self.with_series(name, labels, counter, |s| s.counter(name))?
+    }
+
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Result<Gauge> {
+        self.with_series(name, labels, gauge, |s| s.gauge(name))?
+    }
+
+    pub fn float_gauge(&self, name: &str, labels: &[(&str, &str)]) -> Result<FloatGauge> {
+        self.with_series(name, labels, float_gauge, |s| s.float_gauge(name))?
+    }
+
+    /// A new series gets `DEFAULT_BUCKETS`, use `register_histogram` first for others
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Result<Histogram> {
+        self.with_series(name, labels, histogram, |s| s.histogram(name))?
+    }
+
+    /// A meter with 1/5/15-minute rates, see `Meter`
+    pub fn meter(&self, name: &str, labels: &[(&str, &str)]) -> Result<Meter> {
+        self.with_series(name, labels, || self.new_meter(), |s| s.meter(name))?
+    }
+
+    /// Register `name` as a counter; fine if it already is one
+    pub fn register_counter(&self, name: &str) -> Result<()> {
+        self.register(name, counter())
+    }
+
+    /// Register `name` as a counter sharded across cache lines, see `StripedCounter`
+    ///
+    /// Fails if `name` already exists as a plain counter.
+    pub fn register_striped_counter(&self, name: &str) -> Result<()> {
+        let striped = self.with_series(
+            name,
+            &[],
+            || Slot::Counter(Counter::striped()),
+            |s| s.counter(name).map(|c| c.is_striped()),
+        )??;
+        if !striped {
+            return Err(anyhow::anyhow!(
+                "CmapMetrics error: {} is already a plain counter",
+                name
+            ));
+        }
         Ok(())
     }
+
+    pub fn register_gauge(&self, name: &str) -> Result<()> {
+        self.register(name, gauge())
+    }
+
+    pub fn register_float_gauge(&self, name: &str) -> Result<()> {
+        self.register(name, float_gauge())
+    }
+
+    pub fn register_histogram(&self, name: &str, bounds: &[f64]) -> Result<()> {
+        self.register(name, Slot::Histogram(Histogram::new(bounds)?))
+    }
+
+    pub fn register_meter(&self, name: &str) -> Result<()> {
+        self.register(name, self.new_meter())
+    }
+
+    fn new_meter(&self) -> Slot {
+        Slot::Meter(Meter::new(self.clock.clone()))
+    }
+
+    fn register(&self, name: &str, slot: Slot) -> Result<()> {
+        let kind = slot.kind();
+        let existing = self.with_series(name, &[], || slot, |s| s.kind())?;
+        if existing != kind {
+            return Err(anyhow::anyhow!(
+                "CmapMetrics error: {} is already a {}",
+                name,
+                existing
+            ));
+        }
+        Ok(())
+    }
+
+    // 已有的 key 不需要分配; 没有的 key 按第一次用到的操作决定类型
+    fn with_series<R>(
+        &self,
+        name: &str,
+        labels: &[(&str, &str)],
+        make: impl FnOnce() -> Slot,
+        f: impl FnOnce(&Slot) -> R,
+    ) -> Result<R> {
+        let series = SeriesRef { name, labels };
+        let lookup = &series as &dyn SeriesLookup;
+        if let Some(entry) = self.data.get(lookup) {
+            self.touch(&entry);
+            return Ok(f(&entry.slot));
+        }
+        series.check()?;
+        let Some((limit, policy)) = self.limit else {
+            let key = MetricKey::new(&self.strings, &series)?;
+            let entry = self.data.entry(key).or_insert_with(|| Series::new(make()));
+            return Ok(f(&entry.slot));
+        };
+
+        let _guard = self
+            .inserting
+            .lock()
+            .map_err(|_| anyhow::anyhow!("CmapMetrics error: lock poisoned"))?;
+        // 先用借来的 SeriesRef 判断上限, 放得进去才 intern, 被挡掉的 key 不会留在 Interner 里
+        let overflow = &OVERFLOW_SERIES as &dyn SeriesLookup;
+        let reserved = policy == OverflowPolicy::OverflowKey
+            && lookup != overflow
+            && !self.data.contains_key(overflow);
+        if !self.data.contains_key(lookup) && self.data.len() + usize::from(reserved) >= limit {
+            self.dropped.inc();
+            match policy {
+                OverflowPolicy::Reject => {
+                    return Err(anyhow::anyhow!(
+                        "CmapMetrics error: cardinality limit {} reached, {} dropped",
+                        limit,
+                        lookup.render()
+                    ))
+                }
+                OverflowPolicy::OverflowKey => {
+                    if let Some(entry) = self.data.get(overflow) {
+                        // __overflow__ 的类型由第一次溢出的操作决定, 别的类型的操作
+                        // 落到一个不在表里的临时 slot 上, 只记进 dropped
+                        let slot = make();
+                        if slot.kind() != entry.slot.kind() {
+                            return Ok(f(&slot));
+                        }
+                        return Ok(f(&entry.slot));
+                    }
+                    let key = MetricKey::new(&self.strings, &OVERFLOW_SERIES)?;
+                    let entry = self.data.entry(key).or_insert_with(|| Series::new(make()));
+                    return Ok(f(&entry.slot));
+                }
+                OverflowPolicy::EvictLru => self.evict_lru(),
+            }
+        }
+        let key = MetricKey::new(&self.strings, &series)?;
+        // 新 key 记在加一之前的时刻, 之后对别的 key 的更新都比它新
+        let tick = self.ticks.fetch_add(1, Ordering::Relaxed);
+        let entry = self.data.entry(key).or_insert_with(|| Series::new(make()));
+        entry.touched.store(tick, Ordering::Relaxed);
+        Ok(f(&entry.slot))
+    }
+
+    fn touch(&self, series: &Series) {
+        if let Some((_, OverflowPolicy::EvictLru)) = self.limit {
+            series
+                .touched
+                .store(self.ticks.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    // 扫一遍找最久没更新的, 只在满了又来新 key 时发生;
+    // 它的字符串没有别的 series 在用的话也从 Interner 里删掉
+    fn evict_lru(&self) {
+        let oldest = self
+            .data
+            .iter()
+            .min_by_key(|entry| entry.touched.load(Ordering::Relaxed))
+            .map(|entry| entry.key().clone());
+        if let Some(key) = oldest {
+            let strings = key.strings();
+            drop(self.data.remove(&key));
+            drop(key);
+            self.strings.release(&strings);
+        }
+    }
+}
+
+impl Default for CmapMetrics {
+    fn default() -> Self {
+        Self {
+            data: Arc::default(),
+            strings: Arc::default(),
+            limit: None,
+            dropped: Counter::default(),
+            ticks: Arc::default(),
+            clock: Arc::new(SystemClock),
+            inserting: Arc::default(),
+        }
+    }
+}
+
+impl Series {
+    fn new(slot: Slot) -> Self {
+        Self {
+            slot,
+            touched: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Metrics for CmapMetrics {
+    fn add(&self, key: &str, delta: i64) -> Result<()> {
+        self.with_series(key, &[], gauge, |s| s.add(key, delta))?
+    }
+
+    fn get(&self, key: &str) -> Option<i64> {
+        let series = SeriesRef {
+            name: key,
+            labels: &[],
+        };
+        self.data.get(&series as &dyn SeriesLookup)?.slot.get()
+    }
+
+    fn snapshot(&self) -> MetricsSnapshot {
+        let dropped = self.limit.map(|_| {
+            (
+                DROPPED_OPS.to_string(),
+                MetricValue::Counter(self.dropped.get()),
+            )
+        });
+        MetricsSnapshot::new(
+            self.data
+                .iter()
+                .map(|entry| (entry.key().render(), entry.slot.value()))
+                .chain(dropped),
+        )
+    }
+
+    fn reset(&self) {
+        for entry in self.data.iter() {
+            entry.slot.reset();
+        }
+        self.dropped.reset();
+    }
+}
+
+impl TypedMetrics for CmapMetrics {
+    fn set(&self, key: &str, value: i64) -> Result<()> {
+        self.with_series(key, &[], gauge, |s| s.set(key, value))?
+    }
+
+    fn set_f64(&self, key: &str, value: f64) -> Result<()> {
+        self.with_series(key, &[], float_gauge, |s| s.set_f64(key, value))?
+    }
+
+    fn observe(&self, key: &str, value: f64) -> Result<()> {
+        self.with_series(key, &[], histogram, |s| s.observe(key, value))?
+    }
+
+    fn timer(&self, key: &str) -> Result<Timer> {
+        self.with_series(key, &[], histogram, |s| s.timer(key))?
+    }
+
+    fn counter_handle(&self, key: &str) -> Result<Counter> {
+        self.with_series(key, &[], counter, |s| s.counter(key))?
+    }
+
+    fn gauge_handle(&self, key: &str) -> Result<Gauge> {
+        self.with_series(key, &[], gauge, |s| s.gauge(key))?
+    }
+
+    fn meter_handle(&self, key: &str) -> Result<Meter> {
+        self.with_series(key, &[], || self.new_meter(), |s| s.meter(key))?
+    }
 }
 
 impl Display for CmapMetrics {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for entry in self.data.iter() {
-            writeln!(f, "{}: {}", entry.key(), entry.value())?;
+            writeln!(f, "{}: {}", entry.key().render(), entry.slot.value())?;
         }
         Ok(())
     }
 }
 // endregion: --- impls
+
+fn counter() -> Slot {
+    Slot::Counter(Counter::new())
+}
+
+fn gauge() -> Slot {
+    Slot::Gauge(Gauge::new())
+}
+
+fn float_gauge() -> Slot {
+    Slot::FloatGauge(FloatGauge::new())
+}
+
+fn histogram() -> Slot {
+    Slot::Histogram(Histogram::default())
+}
 // endregion: --- DashMap Version
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{ManualClock, MetricKind};
+
+    #[test]
+    fn test_cmap_creates_on_first_use() -> Result<()> {
+        let clock = ManualClock::new();
+        let metrics = CmapMetrics::new().clock(Arc::new(clock.clone()));
+        metrics.register_counter("requests")?;
+        metrics.register_counter("requests")?;
+        assert!(metrics.register_gauge("requests").is_err());
+        assert!(metrics.register_striped_counter("requests").is_err());
+        metrics.register_striped_counter("hot")?;
+        metrics.register_striped_counter("hot")?;
+        assert!(metrics.counter_handle("hot")?.is_striped());
+        assert!(metrics.dec("requests").is_err());
+
+        // 没注册过的 key 按第一次的操作创建
+        metrics.set_f64("load", 1.5)?;
+        metrics.timer("latency")?.time(|| ());
+        assert_eq!(
+            metrics.snapshot().get("load").map(|v| v.kind()),
+            Some(MetricKind::FloatGauge)
+        );
+        assert_eq!(
+            metrics.snapshot().get("latency").map(|v| v.kind()),
+            Some(MetricKind::Histogram)
+        );
+        assert_eq!(Metrics::get(&metrics, "load"), None);
+
+        // 第一次用到的 meter 读的是 clock 设置的时钟
+        let meter = metrics.meter_handle("req")?;
+        clock.advance(Duration::from_secs(1));
+        meter.add(60);
+        assert_eq!(meter.snapshot().rates[0], 1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_labelled_families() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let page1 = metrics.counter("req", &[("page", "1"), ("method", "GET")])?;
+        page1.inc();
+        metrics
+            .counter("req", &[("method", "GET"), ("page", "1")])?
+            .add(2);
+        metrics
+            .counter("req", &[("page", "2"), ("method", "GET")])?
+            .inc();
+        assert_eq!(page1.get(), 3);
+        assert!(metrics
+            .gauge("req", &[("page", "1"), ("method", "GET")])
+            .is_err());
+        assert!(metrics
+            .counter("req", &[("page", "1"), ("page", "2")])
+            .is_err());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(
+            snapshot.get(r#"req{method="GET",page="1"}"#),
+            Some(&MetricValue::Counter(3))
+        );
+        let families = snapshot.families();
+        assert_eq!(families["req"].len(), 2);
+        assert!(snapshot
+            .to_prometheus()
+            .contains("# TYPE req counter\nreq{method=\"GET\",page=\"1\"} 3\n"));
+
+        metrics.meter("http", &[("page", "1")])?.inc();
+        assert_eq!(metrics.meter("http", &[("page", "1")])?.count(), 1);
+        assert!(metrics.counter("http", &[("page", "1")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_cardinality_limit() -> Result<()> {
+        let reject = CmapMetrics::new().max_cardinality(2, OverflowPolicy::Reject);
+        reject.inc("a")?;
+        reject.inc("b")?;
+        assert!(reject.inc("c").is_err());
+        assert!(reject.counter("req", &[("page", "9")]).is_err());
+        reject.inc("a")?;
+        assert_eq!(reject.dropped_ops(), 2);
+        assert_eq!(
+            reject.snapshot().get(DROPPED_OPS),
+            Some(&MetricValue::Counter(2))
+        );
+        // 数的是操作次数, 不是不同的 key
+        for _ in 0..10 {
+            assert!(reject.inc("c").is_err());
+        }
+        assert_eq!(reject.dropped_ops(), 12);
+        // 被拒绝的 key 和标签不会被 intern
+        assert_eq!(reject.strings.len(), 2);
+        assert!(reject
+            .counter("req", &[("page", "1"), ("page", "2")])
+            .is_err());
+        assert_eq!(reject.dropped_ops(), 12);
+
+        let overflow = CmapMetrics::new().max_cardinality(2, OverflowPolicy::OverflowKey);
+        for page in 0..10 {
+            overflow.inc(format!("req.page.{}", page))?;
+        }
+        // __overflow__ 占了两个位置中的一个
+        assert_eq!(Metrics::get(&overflow, "req.page.0"), Some(1));
+        assert_eq!(Metrics::get(&overflow, "req.page.1"), None);
+        assert_eq!(Metrics::get(&overflow, OVERFLOW_KEY), Some(9));
+        assert_eq!(overflow.dropped_ops(), 9);
+        assert_eq!(overflow.snapshot().len(), 3);
+        assert_eq!(overflow.strings.len(), 2);
+        overflow.reset();
+        assert_eq!(overflow.dropped_ops(), 0);
+
+        // 不同类型的操作溢出时不会报类型不匹配, 只记进 dropped
+        let mixed = CmapMetrics::new().max_cardinality(2, OverflowPolicy::OverflowKey);
+        mixed.counter("req", &[("page", "1")])?.inc();
+        mixed.counter("req", &[("page", "2")])?.inc();
+        mixed.dec("workers")?;
+        mixed.set_f64("load", 0.5)?;
+        mixed.observe("latency", 0.1)?;
+        mixed.gauge("queue", &[])?.set(3);
+        assert_eq!(Metrics::get(&mixed, OVERFLOW_KEY), Some(1));
+        assert_eq!(mixed.dropped_ops(), 5);
+        assert_eq!(mixed.snapshot().len(), 3);
+
+        let lru = CmapMetrics::new().max_cardinality(2, OverflowPolicy::EvictLru);
+        lru.inc("a")?;
+        lru.inc("b")?;
+        lru.inc("a")?;
+        // b 最久没更新, 被挤掉
+        lru.inc("c")?;
+        assert_eq!(Metrics::get(&lru, "a"), Some(2));
+        assert_eq!(Metrics::get(&lru, "b"), None);
+        assert_eq!(Metrics::get(&lru, "c"), Some(1));
+        assert_eq!(lru.dropped_ops(), 1);
+        assert_eq!(lru.snapshot().len(), 3);
+        // 被挤掉的 b 的字符串也释放了, 还在用的不动
+        let lru = CmapMetrics::new().max_cardinality(2, OverflowPolicy::EvictLru);
+        lru.counter("req", &[("page", "1")])?;
+        lru.counter("req", &[("page", "2")])?;
+        lru.counter("req", &[("page", "3")])?;
+        assert_eq!(lru.strings.len(), 4);
+        assert_eq!(lru.snapshot().families()["req"].len(), 2);
+        Ok(())
+    }
+}
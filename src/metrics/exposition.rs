@@ -0,0 +1,587 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+};
+
+use super::{
+    HistogramSnapshot, LabelPairs, MeterSnapshot, MetricKind, MetricValue, MetricsSnapshot,
+};
+
+/// `Content-Type` of the Prometheus text format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+/// `Content-Type` of the OpenMetrics text format
+pub const OPENMETRICS_CONTENT_TYPE: &str =
+    "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Which text exposition format to write
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TextFormat {
+    #[default]
+    Prometheus,
+    /// counters get a `_total` suffix and the output ends with `# EOF`
+    OpenMetrics,
+}
+
+/// Renders a `MetricsSnapshot` in the Prometheus or OpenMetrics text format
+///
+/// Keys are sanitised into valid metric names (`req.page.1` becomes
+/// `req_page_1`). A key of the form `name{k="v",...}` is split into a metric
+/// name and labels, and keys sharing a name are written as one family.
+/// A meter `m` is written as the counter `m` plus the gauges `m_rate` and
+/// `m_ewma`, labelled `window="1m"`, `"5m"` and `"15m"`.
+///
+/// Series that can't be written without breaking the exposition are skipped:
+/// a key whose name sanitises onto a family of another type or repeats its
+/// label set, and a family whose name is already taken, including by the
+/// `_bucket`/`_sum`/`_count` or `_rate`/`_ewma` samples of an earlier family.
+#[derive(Debug, Clone, Default)]
+pub struct TextEncoder {
+    format: TextFormat,
+    help: BTreeMap<String, String>,
+}
+
+// 同一个 family 下的一条样本: 标签和值
+type Sample<'a> = (LabelPairs, &'a MetricValue);
+
+// MeterSnapshot 里三个速率对应的窗口
+const METER_WINDOWS: [&str; 3] = ["1m", "5m", "15m"];
+
+// region:    --- impls
+impl TextFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            TextFormat::Prometheus => PROMETHEUS_CONTENT_TYPE,
+            TextFormat::OpenMetrics => OPENMETRICS_CONTENT_TYPE,
+        }
+    }
+}
+
+impl TextEncoder {
+    pub fn new(format: TextFormat) -> Self {
+        Self {
+            format,
+            help: BTreeMap::new(),
+        }
+    }
+
+    pub fn format(&self) -> TextFormat {
+        self.format
+    }
+
+    /// `# HELP` text for the family `name`, the name is sanitised like the keys
+    pub fn help(mut self, name: &str, text: impl Into<String>) -> Self {
+        self.help.insert(sanitize_name(name), text.into());
+        self
+    }
+
+    pub fn encode(&self, snapshot: &MetricsSnapshot) -> String {
+        self.encode_as(snapshot, self.format)
+    }
+
+    // 同一份 help, 按请求选择格式 (见 MetricsServer)
+    pub(crate) fn encode_as(&self, snapshot: &MetricsSnapshot, format: TextFormat) -> String {
+        let mut families: BTreeMap<String, Vec<Sample>> = BTreeMap::new();
+        // 不同的 key 规范化之后可能变成同一个名字, 再按规范化的名字合并一次
+        for (name, series) in snapshot.families() {
+            let family = families.entry(sanitize_name(name)).or_default();
+            for (labels, value) in series {
+                let labels: LabelPairs = labels
+                    .into_iter()
+                    .map(|(k, v)| (sanitize_label(&k), v))
+                    .collect();
+                // 类型对不上或者标签重复的样本合并不进来, 跳过
+                let clash = family.first().is_some_and(|(_, first)| {
+                    family_kind(first.kind()) != family_kind(value.kind())
+                }) || family.iter().any(|(l, _)| *l == labels);
+                if !clash {
+                    family.push((labels, value));
+                }
+            }
+        }
+
+        let mut out = String::new();
+        // 按名字顺序占用每个 family 写出的样本名, 名字被前面占了的整个 family 跳过;
+        // 生成的名字都以 family 名为前缀, 所以排在前面的总是原 family
+        let mut claimed = HashSet::new();
+        for (name, samples) in &families {
+            let names = sample_names(name, samples[0].1.kind());
+            if names.iter().any(|n| claimed.contains(n)) {
+                continue;
+            }
+            claimed.extend(names);
+            self.write_family(&mut out, name, samples, format);
+        }
+        if format == TextFormat::OpenMetrics {
+            out.push_str("# EOF\n");
+        }
+        out
+    }
+
+    fn write_family(&self, out: &mut String, name: &str, samples: &[Sample], format: TextFormat) {
+        // encode_as 保证了同一个 family 的样本类型一致
+        let kind = samples[0].1.kind();
+        let open = format == TextFormat::OpenMetrics;
+        let family = match kind {
+            MetricKind::Counter | MetricKind::Meter if open => {
+                name.strip_suffix("_total").unwrap_or(name)
+            }
+            _ => name,
+        };
+        if let Some(help) = self.help.get(name).or_else(|| self.help.get(family)) {
+            let _ = writeln!(out, "# HELP {} {}", family, escape_help(help));
+        }
+        let type_name = match kind {
+            MetricKind::Counter | MetricKind::Meter => "counter",
+            MetricKind::Gauge | MetricKind::FloatGauge => "gauge",
+            MetricKind::Histogram => "histogram",
+        };
+        let _ = writeln!(out, "# TYPE {} {}", family, type_name);
+
+        for (labels, value) in samples {
+            match value {
+                MetricValue::Counter(v) if open => {
+                    write_sample(out, &format!("{}_total", family), labels, None, *v as f64)
+                }
+                MetricValue::Meter(m) if open => write_sample(
+                    out,
+                    &format!("{}_total", family),
+                    labels,
+                    None,
+                    m.count as f64,
+                ),
+                MetricValue::Histogram(h) => write_histogram(out, family, labels, h),
+                v => write_sample(out, family, labels, None, v.as_f64()),
+            }
+        }
+        if kind == MetricKind::Meter {
+            let meters = samples
+                .iter()
+                .filter_map(|(labels, value)| match value {
+                    MetricValue::Meter(m) => Some((labels, m)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            write_meter_rates(out, &format!("{}_rate", family), &meters, |m| m.rates);
+            write_meter_rates(out, &format!("{}_ewma", family), &meters, |m| m.ewma);
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// The snapshot in the Prometheus text format, without help text
+    pub fn to_prometheus(&self) -> String {
+        TextEncoder::new(TextFormat::Prometheus).encode(self)
+    }
+
+    /// The snapshot in the OpenMetrics text format, without help text
+    pub fn to_openmetrics(&self) -> String {
+        TextEncoder::new(TextFormat::OpenMetrics).encode(self)
+    }
+
+    /// The snapshot as a JSON object, keys as they are (not sanitised)
+    ///
+    /// `{"timestamp": <unix seconds>, "metrics": {"<key>": {"type": "counter", "value": 1}, ...}}`,
+    /// histograms carry `bounds`, `counts` (not cumulative), `sum` and `count`,
+    /// meters carry `count` and per second `rate` and `ewma` objects keyed by window.
+    pub fn to_json(&self) -> String {
+        let timestamp = self
+            .timestamp()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let mut out = format!("{{\"timestamp\":{},\"metrics\":{{", json_f64(timestamp));
+        for (i, (key, value)) in self.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}:", json_string(key));
+            let _ = match value {
+                MetricValue::Counter(v) => write!(out, r#"{{"type":"counter","value":{}}}"#, v),
+                MetricValue::Gauge(v) => write!(out, r#"{{"type":"gauge","value":{}}}"#, v),
+                MetricValue::FloatGauge(v) => {
+                    write!(out, r#"{{"type":"gauge","value":{}}}"#, json_f64(*v))
+                }
+                MetricValue::Histogram(h) => write!(
+                    out,
+                    r#"{{"type":"histogram","bounds":[{}],"counts":[{}],"sum":{},"count":{}}}"#,
+                    join(h.bounds.iter().map(|b| json_f64(*b))),
+                    join(h.counts.iter()),
+                    json_f64(h.sum),
+                    h.count()
+                ),
+                MetricValue::Meter(m) => write!(
+                    out,
+                    r#"{{"type":"meter","count":{},"rate":{},"ewma":{}}}"#,
+                    m.count,
+                    json_windows(&m.rates),
+                    json_windows(&m.ewma)
+                ),
+            };
+        }
+        out.push_str("}}");
+        out
+    }
+}
+// endregion: --- impls
+
+fn write_histogram(
+    out: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    h: &HistogramSnapshot,
+) {
+    let bucket = format!("{}_bucket", name);
+    let bounds = h.bounds.iter().map(|b| format_f64(*b));
+    for (le, count) in bounds.chain(["+Inf".to_string()]).zip(h.cumulative()) {
+        write_sample(out, &bucket, labels, Some(("le", &le)), count as f64);
+    }
+    write_sample(out, &format!("{}_sum", name), labels, None, h.sum);
+    write_sample(
+        out,
+        &format!("{}_count", name),
+        labels,
+        None,
+        h.count() as f64,
+    );
+}
+
+// 合并时 Gauge 和 FloatGauge 算同一种类型
+fn family_kind(kind: MetricKind) -> MetricKind {
+    match kind {
+        MetricKind::FloatGauge => MetricKind::Gauge,
+        kind => kind,
+    }
+}
+
+// 一个 family 会占用的所有名字, 包括 OpenMetrics 去掉 _total 之后的名字
+fn sample_names(name: &str, kind: MetricKind) -> Vec<String> {
+    let suffixes: &[&str] = match kind {
+        MetricKind::Histogram => &["", "_bucket", "_sum", "_count"],
+        MetricKind::Meter => &["", "_rate", "_ewma"],
+        _ => &[""],
+    };
+    let mut names: Vec<String> = suffixes.iter().map(|s| format!("{}{}", name, s)).collect();
+    if let (MetricKind::Counter | MetricKind::Meter, Some(family)) =
+        (kind, name.strip_suffix("_total"))
+    {
+        names.push(family.to_string());
+    }
+    names
+}
+
+// 每个窗口一条样本, 用 window 标签区分
+fn write_meter_rates(
+    out: &mut String,
+    name: &str,
+    meters: &[(&LabelPairs, &MeterSnapshot)],
+    rates: impl Fn(&MeterSnapshot) -> [f64; 3],
+) {
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    for (labels, m) in meters {
+        for (window, rate) in METER_WINDOWS.iter().zip(rates(m)) {
+            write_sample(out, name, labels, Some(("window", window)), rate);
+        }
+    }
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(String, String)],
+    extra: Option<(&str, &str)>,
+    value: f64,
+) {
+    out.push_str(name);
+    let extra = extra.map(|(k, v)| (k.to_string(), v.to_string()));
+    let mut pairs = labels.iter().chain(extra.as_ref()).peekable();
+    if pairs.peek().is_some() {
+        out.push('{');
+        for (i, (k, v)) in pairs.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", k, escape_label(v));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", format_f64(value));
+}
+
+/// Replace everything outside `[a-zA-Z0-9_:]` with `_`, and prefix a leading digit
+fn sanitize_name(name: &str) -> String {
+    let mut out = sanitize(name, true);
+    if out.is_empty() {
+        out.push('_');
+    }
+    out
+}
+
+// 标签名不允许出现冒号
+fn sanitize_label(name: &str) -> String {
+    let mut out = sanitize(name, false);
+    if out.is_empty() {
+        out.push('_');
+    }
+    out
+}
+
+fn sanitize(name: &str, allow_colon: bool) -> String {
+    let mut out = String::with_capacity(name.len() + 1);
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        out.push('_');
+    }
+    out.extend(name.chars().map(|c| {
+        if c.is_ascii_alphanumeric() || c == '_' || (allow_colon && c == ':') {
+            c
+        } else {
+            '_'
+        }
+    }));
+    out
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_help(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn format_f64(v: f64) -> String {
+    if v.is_nan() {
+        "NaN".to_string()
+    } else if v.is_infinite() {
+        if v > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+// JSON 没有 NaN/Inf, 写成 null
+fn json_f64(v: f64) -> String {
+    if v.is_finite() {
+        v.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+// {"1m":..,"5m":..,"15m":..}
+fn json_windows(rates: &[f64; 3]) -> String {
+    let fields = METER_WINDOWS
+        .iter()
+        .zip(rates)
+        .map(|(w, r)| format!("\"{}\":{}", w, json_f64(*r)));
+    format!("{{{}}}", join(fields))
+}
+
+fn join(items: impl Iterator<Item = impl std::fmt::Display>) -> String {
+    items.map(|i| i.to_string()).collect::<Vec<_>>().join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> MetricsSnapshot {
+        MetricsSnapshot::new([
+            ("req.page.1".to_string(), MetricValue::Gauge(3)),
+            (
+                r#"http{method="GET",path="/a\"b"}"#.to_string(),
+                MetricValue::Counter(7),
+            ),
+            (
+                r#"http{method="POST"}"#.to_string(),
+                MetricValue::Counter(2),
+            ),
+            ("load".to_string(), MetricValue::FloatGauge(0.5)),
+            (
+                "latency".to_string(),
+                MetricValue::Histogram(HistogramSnapshot {
+                    bounds: vec![0.1, 1.0],
+                    counts: vec![1, 2, 1],
+                    sum: 3.25,
+                }),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let text = TextEncoder::new(TextFormat::Prometheus)
+            .help("req.page.1", "requests to\npage 1")
+            .encode(&snapshot());
+        assert_eq!(
+            text,
+            r#"# TYPE http counter
+http{method="GET",path="/a\"b"} 7
+http{method="POST"} 2
+# TYPE latency histogram
+latency_bucket{le="0.1"} 1
+latency_bucket{le="1"} 3
+latency_bucket{le="+Inf"} 4
+latency_sum 3.25
+latency_count 4
+# TYPE load gauge
+load 0.5
+# HELP req_page_1 requests to\npage 1
+# TYPE req_page_1 gauge
+req_page_1 3
+"#
+        );
+    }
+
+    #[test]
+    fn test_openmetrics_text() {
+        let text = TextEncoder::new(TextFormat::OpenMetrics)
+            .help("http", "HTTP requests")
+            .encode(&snapshot());
+        assert!(text.starts_with(
+            "# HELP http HTTP requests\n# TYPE http counter\nhttp_total{method=\"GET\""
+        ));
+        assert!(text.contains("http_total{method=\"POST\"} 2\n"));
+        assert!(text.ends_with("req_page_1 3\n# EOF\n"));
+        assert_eq!(MetricsSnapshot::new([]).to_openmetrics(), "# EOF\n");
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize_name("1req-total:x"), "_1req_total:x");
+        assert_eq!(sanitize_label("x-y:z"), "x_y_z");
+        let snapshot = MetricsSnapshot::new([
+            (r#"a.b{x-y="1"}"#.to_string(), MetricValue::Gauge(1)),
+            (r#"a_b{x-y="2"}"#.to_string(), MetricValue::Gauge(2)),
+        ]);
+        assert_eq!(
+            snapshot.to_prometheus(),
+            "# TYPE a_b gauge\na_b{x_y=\"1\"} 1\na_b{x_y=\"2\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn test_conflicting_families() {
+        let meter = MetricValue::Meter(MeterSnapshot {
+            count: 1,
+            rates: [1.0; 3],
+            ewma: [1.0; 3],
+        });
+        let snapshot = MetricsSnapshot::new([
+            // 规范化成同一个名字但类型不同, 或者标签重复; 按 key 的顺序先到先得
+            ("a.b".to_string(), MetricValue::Gauge(1)),
+            ("a_b".to_string(), MetricValue::Gauge(2)),
+            (r#"a/b{x="1"}"#.to_string(), MetricValue::Counter(3)),
+            // 撞上 meter 和 histogram 生成的名字
+            ("m".to_string(), meter),
+            ("m_rate".to_string(), MetricValue::FloatGauge(0.5)),
+            (
+                "h".to_string(),
+                MetricValue::Histogram(HistogramSnapshot {
+                    bounds: vec![],
+                    counts: vec![1],
+                    sum: 1.0,
+                }),
+            ),
+            ("h_count".to_string(), MetricValue::Counter(9)),
+            ("c".to_string(), MetricValue::Gauge(4)),
+            ("c_total".to_string(), MetricValue::Counter(5)),
+        ]);
+        let text = snapshot.to_prometheus();
+        for line in text.lines().filter(|l| l.starts_with("# TYPE")) {
+            assert_eq!(text.lines().filter(|l| *l == line).count(), 1, "{}", line);
+        }
+        assert!(text.contains("# TYPE a_b gauge\na_b 1\n# TYPE c gauge\nc 4\n"));
+        assert!(text.contains("# TYPE m_rate gauge\nm_rate{window=\"1m\"} 1\n"));
+        assert!(!text.contains("m_rate 0.5"));
+        assert!(!text.contains("h_count 9"));
+        assert!(!text.contains("c_total"));
+        assert!(!text.contains("a_b 2") && !text.contains("a_b{"));
+    }
+
+    #[test]
+    fn test_json() {
+        let snapshot = MetricsSnapshot::with_timestamp(
+            std::time::UNIX_EPOCH + std::time::Duration::from_millis(1500),
+            [
+                ("a\"b".to_string(), MetricValue::Counter(1)),
+                ("nan".to_string(), MetricValue::FloatGauge(f64::NAN)),
+                (
+                    "h".to_string(),
+                    MetricValue::Histogram(HistogramSnapshot {
+                        bounds: vec![0.5],
+                        counts: vec![1, 2],
+                        sum: 4.0,
+                    }),
+                ),
+            ],
+        );
+        assert_eq!(
+            snapshot.to_json(),
+            concat!(
+                r#"{"timestamp":1.5,"metrics":{"a\"b":{"type":"counter","value":1},"#,
+                r#""h":{"type":"histogram","bounds":[0.5],"counts":[1,2],"sum":4,"count":3},"#,
+                r#""nan":{"type":"gauge","value":null}}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_meter() {
+        let meter = MetricValue::Meter(MeterSnapshot {
+            count: 12,
+            rates: [2.0, 0.5, 0.25],
+            ewma: [1.5, 0.5, 0.0],
+        });
+        let snapshot = MetricsSnapshot::with_timestamp(
+            std::time::UNIX_EPOCH,
+            [(r#"req{page="1"}"#.to_string(), meter)],
+        );
+        assert_eq!(
+            snapshot.to_prometheus(),
+            r#"# TYPE req counter
+req{page="1"} 12
+# TYPE req_rate gauge
+req_rate{page="1",window="1m"} 2
+req_rate{page="1",window="5m"} 0.5
+req_rate{page="1",window="15m"} 0.25
+# TYPE req_ewma gauge
+req_ewma{page="1",window="1m"} 1.5
+req_ewma{page="1",window="5m"} 0.5
+req_ewma{page="1",window="15m"} 0
+"#
+        );
+        assert!(snapshot
+            .to_openmetrics()
+            .starts_with("# TYPE req counter\nreq_total{page=\"1\"} 12\n"));
+        assert_eq!(
+            snapshot.to_json(),
+            concat!(
+                r#"{"timestamp":0,"metrics":{"req{page=\"1\"}":{"type":"meter","count":12,"#,
+                r#""rate":{"1m":2,"5m":0.5,"15m":0.25},"ewma":{"1m":1.5,"5m":0.5,"15m":0}}}}"#
+            )
+        );
+    }
+}
@@ -0,0 +1,622 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+
+use super::{striped::saturating_add, Meter, MetricValue, StripedCounter};
+
+/// Prometheus' default histogram buckets, in seconds
+pub const DEFAULT_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// What a metric measures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// only goes up
+    Counter,
+    /// an i64 that can go up and down or be set
+    Gauge,
+    /// an f64 that can be set
+    FloatGauge,
+    /// observations counted into fixed buckets
+    Histogram,
+    /// a count with 1/5/15-minute rates
+    Meter,
+}
+
+// 每种 metric 都只是 Arc 包着的原子变量, clone 出来的句柄和注册表里的是同一份数据
+
+/// A monotonic counter, a single atomic or a `StripedCounter`
+#[derive(Debug, Clone)]
+pub struct Counter(CounterCell);
+
+#[derive(Debug, Clone)]
+enum CounterCell {
+    Single(Arc<AtomicI64>),
+    Striped(StripedCounter),
+}
+
+/// An i64 gauge
+#[derive(Debug, Clone, Default)]
+pub struct Gauge(Arc<AtomicI64>);
+
+/// An f64 gauge, stored as bits in an `AtomicU64`
+#[derive(Debug, Clone, Default)]
+pub struct FloatGauge(Arc<AtomicU64>);
+
+/// Counts observations into fixed buckets, `value <= bound`, plus a `+Inf` bucket
+#[derive(Debug, Clone)]
+pub struct Histogram(Arc<HistogramCore>);
+
+#[derive(Debug)]
+struct HistogramCore {
+    bounds: Box<[f64]>,
+    /// one more than `bounds`, the last one is `+Inf`
+    buckets: Box<[AtomicU64]>,
+    /// f64 bits
+    sum: AtomicU64,
+}
+
+/// The buckets of a histogram at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistogramSnapshot {
+    /// upper bounds, `+Inf` is implied
+    pub bounds: Vec<f64>,
+    /// observations per bucket, not cumulative; one more than `bounds`
+    pub counts: Vec<u64>,
+    pub sum: f64,
+}
+
+/// Records elapsed time, in seconds, into a histogram
+#[derive(Debug, Clone)]
+pub struct Timer(Histogram);
+
+/// Records the time since `Timer::start` when dropped
+#[derive(Debug)]
+pub struct TimerGuard {
+    histogram: Histogram,
+    start: Instant,
+}
+
+// 注册表里存的值, 按 kind 分派操作
+#[derive(Debug, Clone)]
+pub(crate) enum Slot {
+    Counter(Counter),
+    Gauge(Gauge),
+    FloatGauge(FloatGauge),
+    Histogram(Histogram),
+    Meter(Meter),
+}
+
+// region:    --- impls
+impl Counter {
+    pub fn new() -> Self {
+        Self(CounterCell::Single(Arc::default()))
+    }
+
+    /// Sharded across cache lines, for counters hit by many threads at once
+    pub fn striped() -> Self {
+        Self(CounterCell::Striped(StripedCounter::new()))
+    }
+
+    pub fn is_striped(&self) -> bool {
+        matches!(self.0, CounterCell::Striped(_))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Saturates at `i64::MAX`, a counter never goes down
+    pub fn add(&self, n: u64) {
+        let n = i64::try_from(n).unwrap_or(i64::MAX);
+        match &self.0 {
+            CounterCell::Single(v) => saturating_add(v, n),
+            CounterCell::Striped(v) => v.add(n),
+        }
+    }
+
+    pub fn get(&self) -> i64 {
+        match &self.0 {
+            CounterCell::Single(v) => v.load(Ordering::Relaxed),
+            CounterCell::Striped(v) => v.get(),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        match &self.0 {
+            CounterCell::Single(v) => v.store(0, Ordering::Relaxed),
+            CounterCell::Striped(v) => v.reset(),
+        }
+    }
+}
+
+impl Default for Counter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn dec(&self) {
+        self.add(-1);
+    }
+
+    pub fn add(&self, delta: i64) {
+        self.0.fetch_add(delta, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, value: i64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl FloatGauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, delta: f64) {
+        add_f64(&self.0, delta);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl Histogram {
+    /// `bounds` must be finite and strictly increasing
+    pub fn new(bounds: &[f64]) -> Result<Self> {
+        if bounds.iter().any(|b| !b.is_finite()) || bounds.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(anyhow::anyhow!(
+                "Histogram error: bounds must be finite and strictly increasing"
+            ));
+        }
+        Ok(Self(Arc::new(HistogramCore {
+            bounds: bounds.into(),
+            buckets: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum: AtomicU64::new(0),
+        })))
+    }
+
+    /// NaN and infinite values are ignored, they would poison `sum` for good
+    pub fn observe(&self, value: f64) {
+        if !value.is_finite() {
+            return;
+        }
+        let core = &self.0;
+        let idx = core.bounds.partition_point(|&b| b < value);
+        core.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        add_f64(&core.sum, value);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let core = &self.0;
+        HistogramSnapshot {
+            bounds: core.bounds.to_vec(),
+            counts: core
+                .buckets
+                .iter()
+                .map(|b| b.load(Ordering::Relaxed))
+                .collect(),
+            sum: f64::from_bits(core.sum.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn reset(&self) {
+        for bucket in self.0.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.0.sum.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS).expect("default buckets are valid")
+    }
+}
+
+impl HistogramSnapshot {
+    /// Total number of observations
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Observations `<= bound` for every bound and `+Inf`, as Prometheus exposes them
+    pub fn cumulative(&self) -> Vec<u64> {
+        self.counts
+            .iter()
+            .scan(0, |acc, &c| {
+                *acc += c;
+                Some(*acc)
+            })
+            .collect()
+    }
+}
+
+impl Timer {
+    pub fn new(histogram: Histogram) -> Self {
+        Self(histogram)
+    }
+
+    pub fn start(&self) -> TimerGuard {
+        TimerGuard {
+            histogram: self.0.clone(),
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&self, elapsed: Duration) {
+        self.0.observe(elapsed.as_secs_f64());
+    }
+
+    /// Run `f` and record how long it took
+    pub fn time<R>(&self, f: impl FnOnce() -> R) -> R {
+        let _guard = self.start();
+        f()
+    }
+
+    pub fn histogram(&self) -> &Histogram {
+        &self.0
+    }
+}
+
+impl Drop for TimerGuard {
+    fn drop(&mut self) {
+        self.histogram.observe(self.start.elapsed().as_secs_f64());
+    }
+}
+
+impl Slot {
+    pub(crate) fn kind(&self) -> MetricKind {
+        match self {
+            Slot::Counter(_) => MetricKind::Counter,
+            Slot::Gauge(_) => MetricKind::Gauge,
+            Slot::FloatGauge(_) => MetricKind::FloatGauge,
+            Slot::Histogram(_) => MetricKind::Histogram,
+            Slot::Meter(_) => MetricKind::Meter,
+        }
+    }
+
+    pub(crate) fn add(&self, key: &str, delta: i64) -> Result<()> {
+        match self {
+            Slot::Counter(_) | Slot::Meter(_) if delta < 0 => Err(anyhow::anyhow!(
+                "metric {} is a {} and can't decrease",
+                key,
+                self.kind()
+            )),
+            Slot::Counter(c) => {
+                c.add(delta as u64);
+                Ok(())
+            }
+            Slot::Gauge(g) => {
+                g.add(delta);
+                Ok(())
+            }
+            Slot::FloatGauge(g) => {
+                g.add(delta as f64);
+                Ok(())
+            }
+            Slot::Meter(m) => {
+                m.add(delta as u64);
+                Ok(())
+            }
+            Slot::Histogram(_) => Err(self.mismatch(key, "add")),
+        }
+    }
+
+    pub(crate) fn set(&self, key: &str, value: i64) -> Result<()> {
+        match self {
+            Slot::Gauge(g) => g.set(value),
+            Slot::FloatGauge(g) => g.set(value as f64),
+            _ => return Err(self.mismatch(key, "set")),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn set_f64(&self, key: &str, value: f64) -> Result<()> {
+        match self {
+            Slot::FloatGauge(g) => g.set(value),
+            _ => return Err(self.mismatch(key, "set_f64")),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn observe(&self, key: &str, value: f64) -> Result<()> {
+        match self {
+            Slot::Histogram(_) if !value.is_finite() => Err(anyhow::anyhow!(
+                "metric {} can't observe non-finite value {}",
+                key,
+                value
+            )),
+            Slot::Histogram(h) => {
+                h.observe(value);
+                Ok(())
+            }
+            _ => Err(self.mismatch(key, "observe")),
+        }
+    }
+
+    pub(crate) fn timer(&self, key: &str) -> Result<Timer> {
+        match self {
+            Slot::Histogram(h) => Ok(Timer::new(h.clone())),
+            _ => Err(self.mismatch(key, "timer")),
+        }
+    }
+
+    pub(crate) fn counter(&self, key: &str) -> Result<Counter> {
+        match self {
+            Slot::Counter(c) => Ok(c.clone()),
+            _ => Err(self.mismatch(key, "counter")),
+        }
+    }
+
+    pub(crate) fn gauge(&self, key: &str) -> Result<Gauge> {
+        match self {
+            Slot::Gauge(g) => Ok(g.clone()),
+            _ => Err(self.mismatch(key, "gauge")),
+        }
+    }
+
+    pub(crate) fn float_gauge(&self, key: &str) -> Result<FloatGauge> {
+        match self {
+            Slot::FloatGauge(g) => Ok(g.clone()),
+            _ => Err(self.mismatch(key, "float_gauge")),
+        }
+    }
+
+    pub(crate) fn histogram(&self, key: &str) -> Result<Histogram> {
+        match self {
+            Slot::Histogram(h) => Ok(h.clone()),
+            _ => Err(self.mismatch(key, "histogram")),
+        }
+    }
+
+    pub(crate) fn meter(&self, key: &str) -> Result<Meter> {
+        match self {
+            Slot::Meter(m) => Ok(m.clone()),
+            _ => Err(self.mismatch(key, "meter")),
+        }
+    }
+
+    pub(crate) fn get(&self) -> Option<i64> {
+        match self {
+            Slot::Counter(c) => Some(c.get()),
+            Slot::Gauge(g) => Some(g.get()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn value(&self) -> MetricValue {
+        match self {
+            Slot::Counter(c) => MetricValue::Counter(c.get()),
+            Slot::Gauge(g) => MetricValue::Gauge(g.get()),
+            Slot::FloatGauge(g) => MetricValue::FloatGauge(g.get()),
+            Slot::Histogram(h) => MetricValue::Histogram(h.snapshot()),
+            Slot::Meter(m) => MetricValue::Meter(m.snapshot()),
+        }
+    }
+
+    // 原地清零, 已经拿到的句柄继续有效
+    pub(crate) fn reset(&self) {
+        match self {
+            Slot::Counter(c) => c.reset(),
+            Slot::Gauge(g) => g.set(0),
+            Slot::FloatGauge(g) => g.set(0.0),
+            Slot::Histogram(h) => h.reset(),
+            Slot::Meter(m) => m.reset(),
+        }
+    }
+
+    fn mismatch(&self, key: &str, op: &str) -> anyhow::Error {
+        anyhow::anyhow!(
+            "metric {} is a {}, {} is not supported",
+            key,
+            self.kind(),
+            op
+        )
+    }
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+            MetricKind::FloatGauge => "float gauge",
+            MetricKind::Histogram => "histogram",
+            MetricKind::Meter => "meter",
+        };
+        f.write_str(name)
+    }
+}
+// endregion: --- impls
+
+fn add_f64(cell: &AtomicU64, delta: f64) {
+    // 没有原生的 f64 fetch_add, 用 CAS 循环
+    let _ = cell.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        Some((f64::from_bits(bits) + delta).to_bits())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets() -> Result<()> {
+        let h = Histogram::new(&[1.0, 5.0])?;
+        for v in [0.5, 1.0, 3.0, 7.0, 100.0] {
+            h.observe(v);
+        }
+        let s = h.snapshot();
+        assert_eq!(s.counts, vec![2, 1, 2]);
+        assert_eq!(s.cumulative(), vec![2, 3, 5]);
+        assert_eq!(s.count(), 5);
+        assert_eq!(s.sum, 111.5);
+
+        assert!(Histogram::new(&[1.0, 1.0]).is_err());
+        assert!(Histogram::new(&[f64::NAN]).is_err());
+
+        // NaN 和无穷大直接丢掉, 不然 sum 永远是 NaN
+        h.observe(f64::NAN);
+        h.observe(f64::INFINITY);
+        assert_eq!(h.snapshot(), s);
+        Ok(())
+    }
+
+    #[test]
+    fn test_counter_add_saturates() {
+        let counter = Counter::new();
+        counter.add(u64::MAX);
+        assert_eq!(counter.get(), i64::MAX);
+        let striped = Counter::striped();
+        striped.add(1 << 63);
+        assert_eq!(striped.get(), i64::MAX);
+
+        // 从非零值开始加也不会回绕成负数
+        for counter in [Counter::new(), Counter::striped()] {
+            counter.add(1);
+            counter.add(u64::MAX);
+            assert_eq!(counter.get(), i64::MAX);
+            counter.inc();
+            assert_eq!(counter.get(), i64::MAX);
+        }
+    }
+
+    #[test]
+    fn test_float_gauge_concurrent_add() {
+        let g = FloatGauge::new();
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        g.add(0.5);
+                    }
+                });
+            }
+        });
+        assert_eq!(g.get(), 2000.0);
+        g.set(-1.25);
+        assert_eq!(g.get(), -1.25);
+    }
+
+    #[test]
+    fn test_timer_records_into_histogram() {
+        let timer = Timer::new(Histogram::default());
+        let out = timer.time(|| 42);
+        timer.record(Duration::from_millis(30));
+        assert_eq!(out, 42);
+        let s = timer.histogram().snapshot();
+        assert_eq!(s.count(), 2);
+        assert!(s.sum >= 0.03);
+    }
+
+    #[test]
+    fn test_slot_kind_checks() {
+        let counter = Slot::Counter(Counter::new());
+        assert!(counter.add("c", 2).is_ok());
+        assert!(counter.add("c", -1).is_err());
+        assert!(counter.set("c", 1).is_err());
+        assert_eq!(counter.get(), Some(2));
+
+        let hist = Slot::Histogram(Histogram::default());
+        assert!(hist.add("h", 1).is_err());
+        assert!(hist.observe("h", 0.2).is_ok());
+        assert!(hist.observe("h", f64::NAN).is_err());
+        assert_eq!(hist.get(), None);
+
+        let gauge = Slot::Gauge(Gauge::new());
+        assert!(gauge.counter("g").is_err());
+        hist.reset();
+        assert!(matches!(hist.value(), MetricValue::Histogram(s) if s.count() == 0));
+    }
+
+    // 两个后端共用的语义: 各类型支持哪些操作, 句柄和表里的值是同一份, reset 之后句柄仍然有效
+    #[test]
+    fn test_slot_handles_and_kinds() -> Result<()> {
+        let counter = Slot::Counter(Counter::new());
+        let handle = counter.counter("c")?;
+        handle.inc();
+        counter.add("c", 2)?;
+        assert!(counter.gauge("c").is_err());
+        counter.reset();
+        handle.add(2);
+        assert_eq!(counter.get(), Some(2));
+        assert_eq!(counter.value(), MetricValue::Counter(2));
+
+        let gauge = Slot::Gauge(Gauge::new());
+        let handle = gauge.gauge("g")?;
+        handle.set(5);
+        gauge.reset();
+        handle.dec();
+        gauge.add("g", -1)?;
+        assert_eq!(gauge.get(), Some(-2));
+        assert!(gauge.observe("g", 1.0).is_err());
+
+        let load = Slot::FloatGauge(FloatGauge::new());
+        load.set_f64("load", 0.75)?;
+        assert_eq!(load.get(), None);
+        assert_eq!(load.value(), MetricValue::FloatGauge(0.75));
+
+        let latency = Slot::Histogram(Histogram::new(&[0.1, 1.0])?);
+        latency.observe("latency", 0.5)?;
+        latency.timer("latency")?.record(Duration::from_secs(2));
+        assert!(matches!(latency.value(), MetricValue::Histogram(h) if h.counts == [0, 1, 1]));
+
+        let striped = Slot::Counter(Counter::striped());
+        let hot = striped.counter("hot")?;
+        assert!(hot.is_striped());
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        hot.inc();
+                        striped.add("hot", 1).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(striped.get(), Some(8000));
+
+        let clock = crate::ManualClock::new();
+        let meter = Slot::Meter(Meter::new(Arc::new(clock.clone())));
+        let handle = meter.meter("req")?;
+        clock.advance(Duration::from_secs(1));
+        handle.add(2);
+        meter.add("req", 1)?;
+        assert!(meter.add("req", -1).is_err());
+        assert_eq!(meter.get(), None);
+        assert!(matches!(meter.value(), MetricValue::Meter(m) if m.count == 3));
+        meter.reset();
+        assert_eq!(handle.count(), 0);
+        Ok(())
+    }
+}
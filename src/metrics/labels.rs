@@ -0,0 +1,309 @@
+use std::{
+    borrow::Borrow,
+    fmt::Write,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Result};
+use dashmap::DashSet;
+
+/// Label name/value pairs of one series
+pub type LabelPairs = Vec<(String, String)>;
+
+// CmapMetrics 的 key: 名字加上一组标签, 字符串都是 intern 过的 Arc<str>
+#[derive(Debug, Clone)]
+pub(crate) struct MetricKey {
+    name: Arc<str>,
+    labels: Arc<[(Arc<str>, Arc<str>)]>,
+}
+
+/// A borrowed `MetricKey`, for lookups that don't allocate
+pub(crate) struct SeriesRef<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) labels: &'a [(&'a str, &'a str)],
+}
+
+// MetricKey 和 SeriesRef 共同的视图, DashMap 通过 Borrow<dyn SeriesLookup> 用 SeriesRef 查找.
+// 标签顺序不影响 hash 和相等, 所以查找前不用排序
+pub(crate) trait SeriesLookup {
+    fn name(&self) -> &str;
+    fn label_count(&self) -> usize;
+    fn label(&self, i: usize) -> (&str, &str);
+
+    /// `name{k="v",...}` with labels sorted by name, or just `name`
+    fn render(&self) -> String {
+        if self.label_count() == 0 {
+            return self.name().to_string();
+        }
+        let mut labels = (0..self.label_count())
+            .map(|i| self.label(i))
+            .collect::<Vec<_>>();
+        labels.sort();
+        let mut out = format!("{}{{", self.name());
+        for (i, (k, v)) in labels.into_iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"", k);
+            for c in v.chars() {
+                match c {
+                    '\\' => out.push_str("\\\\"),
+                    '"' => out.push_str("\\\""),
+                    '\n' => out.push_str("\\n"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+        out.push('}');
+        out
+    }
+}
+
+/// Shares one allocation between every copy of a label name or value
+#[derive(Debug, Default)]
+pub(crate) struct Interner(DashSet<Arc<str>>);
+
+// region:    --- impls
+impl MetricKey {
+    /// Fails if a label name appears twice
+    pub(crate) fn new(interner: &Interner, series: &SeriesRef) -> Result<Self> {
+        series.check()?;
+        let mut labels = series
+            .labels
+            .iter()
+            .map(|(k, v)| (interner.intern(k), interner.intern(v)))
+            .collect::<Vec<_>>();
+        labels.sort();
+        Ok(Self {
+            name: interner.intern(series.name),
+            labels: labels.into(),
+        })
+    }
+
+    // 名字和标签里的字符串, Interner::release 用
+    pub(crate) fn strings(&self) -> Vec<String> {
+        let labels = self
+            .labels
+            .iter()
+            .flat_map(|(k, v)| [k.to_string(), v.to_string()]);
+        std::iter::once(self.name.to_string())
+            .chain(labels)
+            .collect()
+    }
+}
+
+impl SeriesRef<'_> {
+    /// Fails if a label name appears twice, checked before anything is interned
+    pub(crate) fn check(&self) -> Result<()> {
+        let mut names = self.labels.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+        names.sort_unstable();
+        if let Some(w) = names.windows(2).find(|w| w[0] == w[1]) {
+            return Err(anyhow!(
+                "metric {} error: label {} given twice",
+                self.name,
+                w[0]
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl SeriesLookup for MetricKey {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn label(&self, i: usize) -> (&str, &str) {
+        let (k, v) = &self.labels[i];
+        (k, v)
+    }
+}
+
+impl SeriesLookup for SeriesRef<'_> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn label_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    fn label(&self, i: usize) -> (&str, &str) {
+        self.labels[i]
+    }
+}
+
+impl Hash for dyn SeriesLookup + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name().hash(state);
+        // 每个标签单独 hash 再相加, 与顺序无关
+        let labels = (0..self.label_count()).fold(0u64, |acc, i| {
+            let mut h = DefaultHasher::new();
+            self.label(i).hash(&mut h);
+            acc.wrapping_add(h.finish())
+        });
+        state.write_u64(labels);
+    }
+}
+
+impl PartialEq for dyn SeriesLookup + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        // 标签名不会重复, 所以数量相同且互相包含就是同一组标签
+        self.name() == other.name()
+            && self.label_count() == other.label_count()
+            && (0..self.label_count())
+                .all(|i| (0..other.label_count()).any(|j| self.label(i) == other.label(j)))
+    }
+}
+
+impl Eq for dyn SeriesLookup + '_ {}
+
+impl<'a> Borrow<dyn SeriesLookup + 'a> for MetricKey {
+    fn borrow(&self) -> &(dyn SeriesLookup + 'a) {
+        self
+    }
+}
+
+impl Hash for MetricKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self as &dyn SeriesLookup).hash(state)
+    }
+}
+
+impl PartialEq for MetricKey {
+    fn eq(&self, other: &Self) -> bool {
+        (self as &dyn SeriesLookup) == (other as &dyn SeriesLookup)
+    }
+}
+
+impl Eq for MetricKey {}
+
+impl Interner {
+    pub(crate) fn intern(&self, s: &str) -> Arc<str> {
+        if let Some(existing) = self.0.get(s) {
+            return existing.clone();
+        }
+        let s: Arc<str> = s.into();
+        // 并发插入同一个字符串时, 后来的那个直接被丢掉也没关系
+        self.0.insert(s.clone());
+        s
+    }
+
+    /// Forget the strings no `MetricKey` uses any more
+    pub(crate) fn release(&self, strings: &[String]) {
+        for s in strings {
+            // 判断和删除都在分片的写锁里, intern 正在拿的字符串引用数不会是 1
+            self.0.remove_if(s.as_str(), |s| Arc::strong_count(s) == 1);
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.0.len()
+    }
+}
+// endregion: --- impls
+
+/// Split a `name{k="v",...}` key into its name and labels; a key that doesn't
+/// parse is all name
+pub(crate) fn split_key(key: &str) -> (&str, LabelPairs) {
+    if let Some((name, rest)) = key.split_once('{') {
+        if let Some(labels) = rest.strip_suffix('}').and_then(parse_labels) {
+            return (name, labels);
+        }
+    }
+    (key, Vec::new())
+}
+
+fn parse_labels(s: &str) -> Option<LabelPairs> {
+    let mut labels = Vec::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        if chars.peek().is_none() {
+            return Some(labels);
+        }
+        let name = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect::<String>();
+        if chars.next() != Some('=') || chars.next() != Some('"') {
+            return None;
+        }
+        let mut value = String::new();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => match chars.next()? {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+        labels.push((name.trim().to_string(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup_ignores_label_order() -> Result<()> {
+        let interner = Interner::default();
+        let key = MetricKey::new(
+            &interner,
+            &SeriesRef {
+                name: "req",
+                labels: &[("page", "1"), ("method", "GET")],
+            },
+        )?;
+        let set = HashSet::from([key.clone()]);
+        let swapped = SeriesRef {
+            name: "req",
+            labels: &[("method", "GET"), ("page", "1")],
+        };
+        assert!(set.contains(&swapped as &dyn SeriesLookup));
+        let other = SeriesRef {
+            name: "req",
+            labels: &[("page", "2"), ("method", "GET")],
+        };
+        assert!(!set.contains(&other as &dyn SeriesLookup));
+
+        assert_eq!(key.render(), r#"req{method="GET",page="1"}"#);
+        let dup = SeriesRef {
+            name: "req",
+            labels: &[("page", "1"), ("page", "2")],
+        };
+        assert!(MetricKey::new(&interner, &dup).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_and_split_round_trip() -> Result<()> {
+        let interner = Interner::default();
+        let series = SeriesRef {
+            name: "a.b",
+            labels: &[("path", "/x\"y\\z\n")],
+        };
+        let rendered = MetricKey::new(&interner, &series)?.render();
+        assert_eq!(
+            split_key(&rendered),
+            ("a.b", vec![("path".to_string(), "/x\"y\\z\n".to_string())])
+        );
+        assert_eq!(split_key("plain"), ("plain", vec![]));
+        assert_eq!(split_key("a{broken"), ("a{broken", vec![]));
+        // 同一个字符串只分配一次
+        assert!(Arc::ptr_eq(
+            &interner.intern("path"),
+            &interner.intern("path")
+        ));
+        Ok(())
+    }
+}
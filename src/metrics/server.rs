@@ -0,0 +1,383 @@
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+    time::timeout,
+};
+use tracing::{info, warn};
+
+use super::{Metrics, TextEncoder, TextFormat};
+
+// 只读请求头, 超过这个长度直接拒绝
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+// accept 出错 (比如 fd 用完) 之后歇一下再接着 accept, 免得空转
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+
+/// A minimal HTTP/1.1 server exposing a metrics registry
+///
+/// - `GET /metrics`: Prometheus text, or OpenMetrics if the `Accept` header asks for it
+/// - `GET /metrics.json`: the snapshot as JSON
+///
+/// Every response closes the connection, scrapers open a new one each time.
+/// Reading the request and writing the response each have `request_timeout`
+/// (10s by default), a connection that runs over is dropped.
+pub struct MetricsServer {
+    metrics: Arc<dyn Metrics>,
+    encoder: TextEncoder,
+    request_timeout: Duration,
+    shutdown_grace: Duration,
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+// region:    --- impls
+impl MetricsServer {
+    pub fn new(metrics: Arc<dyn Metrics>) -> Self {
+        Self {
+            metrics,
+            encoder: TextEncoder::default(),
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            shutdown_grace: DEFAULT_SHUTDOWN_GRACE,
+        }
+    }
+
+    /// How long reading a request, and then writing its response, may take
+    pub fn request_timeout(mut self, request_timeout: Duration) -> Self {
+        self.request_timeout = request_timeout;
+        self
+    }
+
+    /// How long a shutdown waits for the requests in flight before aborting them
+    pub fn shutdown_grace(mut self, shutdown_grace: Duration) -> Self {
+        self.shutdown_grace = shutdown_grace;
+        self
+    }
+
+    /// `# HELP` text for the family `name`
+    pub fn help(mut self, name: &str, text: impl Into<String>) -> Self {
+        self.encoder = self.encoder.help(name, text);
+        self
+    }
+
+    /// Serve forever; accept errors are logged and retried, use
+    /// `serve_with_shutdown` to stop the server
+    pub async fn serve(self, listener: TcpListener) -> Result<()> {
+        self.serve_with_shutdown(listener, std::future::pending())
+            .await
+    }
+
+    /// Serve until `shutdown` completes, then stop accepting and wait up to
+    /// `shutdown_grace` for the requests in flight, aborting the rest
+    ///
+    /// Errors accepting a connection are logged and don't stop the server.
+    pub async fn serve_with_shutdown(
+        self,
+        listener: TcpListener,
+        shutdown: impl Future<Output = ()>,
+    ) -> Result<()> {
+        let server = Arc::new(self);
+        let mut conns = JoinSet::new();
+        tokio::pin!(shutdown);
+        info!("Serving metrics on {}", listener.local_addr()?);
+        loop {
+            tokio::select! {
+                _ = &mut shutdown => break,
+                accepted = listener.accept() => {
+                    let (stream, remote_addr) = match accepted {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            warn!("Error accepting metrics connection: {:?}", e);
+                            tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                            continue;
+                        }
+                    };
+                    let server = server.clone();
+                    conns.spawn(async move {
+                        if let Err(e) = server.process_conn(stream).await {
+                            warn!("Error serving metrics to {}: {:?}", remote_addr, e);
+                        }
+                    });
+                }
+                // 回收已经结束的连接, 避免 JoinSet 一直变大
+                Some(_) = conns.join_next(), if !conns.is_empty() => {}
+            }
+        }
+        drop(listener);
+        let drain = async { while conns.join_next().await.is_some() {} };
+        if timeout(server.shutdown_grace, drain).await.is_err() {
+            warn!(
+                "Aborting {} metrics connections after shutdown grace",
+                conns.len()
+            );
+            conns.abort_all();
+            while conns.join_next().await.is_some() {}
+        }
+        info!("Metrics server shut down");
+        Ok(())
+    }
+
+    async fn process_conn(&self, mut stream: TcpStream) -> Result<()> {
+        let head = timeout(self.request_timeout, read_head(&mut stream))
+            .await
+            .map_err(|_| anyhow!("HTTP error: timed out reading request"))??;
+        let Some(head) = head else {
+            return Ok(());
+        };
+        let response = self.respond(&head);
+        let write = write_response(&mut stream, &response, head.method != "HEAD");
+        timeout(self.request_timeout, write)
+            .await
+            .map_err(|_| anyhow!("HTTP error: timed out writing response"))?
+    }
+
+    fn respond(&self, head: &RequestHead) -> Response {
+        if head.method != "GET" && head.method != "HEAD" {
+            return Response::text("405 Method Not Allowed", "method not allowed\n");
+        }
+        match head.path.as_str() {
+            "/metrics" => {
+                let format = if head.accept.contains("application/openmetrics-text") {
+                    TextFormat::OpenMetrics
+                } else {
+                    TextFormat::Prometheus
+                };
+                Response {
+                    status: "200 OK",
+                    content_type: format.content_type(),
+                    body: self.encoder.encode_as(&self.metrics.snapshot(), format),
+                }
+            }
+            "/metrics.json" => Response {
+                status: "200 OK",
+                content_type: "application/json",
+                body: self.metrics.snapshot().to_json(),
+            },
+            _ => Response::text("404 Not Found", "not found\n"),
+        }
+    }
+}
+
+impl Response {
+    fn text(status: &'static str, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.to_string(),
+        }
+    }
+}
+// endregion: --- impls
+
+#[derive(Debug)]
+struct RequestHead {
+    method: String,
+    path: String,
+    accept: String,
+}
+
+/// Read the request line and headers, `None` if the client closed without sending anything
+async fn read_head(stream: &mut TcpStream) -> Result<Option<RequestHead>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    let end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_REQUEST_HEAD {
+            return Err(anyhow!("HTTP error: request head too large"));
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            return Err(anyhow!("HTTP error: connection closed mid request"));
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = std::str::from_utf8(&buf[..end])?;
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(anyhow!("HTTP error: bad request line"));
+    };
+    // 忽略 query string
+    let path = target.split('?').next().unwrap_or(target);
+    let accept = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("accept"))
+        .map(|(_, value)| value.trim().to_string())
+        .unwrap_or_default();
+    Ok(Some(RequestHead {
+        method: method.to_string(),
+        path: path.to_string(),
+        accept,
+    }))
+}
+
+// HEAD 只写响应头, Content-Length 还是 body 的长度
+async fn write_response(stream: &mut TcpStream, response: &Response, body: bool) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    if body {
+        stream.write_all(response.body.as_bytes()).await?;
+    }
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Bind `addr` and serve `metrics` on it until `shutdown` completes
+pub async fn serve_metrics(
+    addr: SocketAddr,
+    metrics: Arc<dyn Metrics>,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    MetricsServer::new(metrics)
+        .serve_with_shutdown(listener, shutdown)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{sync::oneshot, task::JoinHandle};
+
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics};
+
+    async fn request(addr: SocketAddr, head: &str) -> Result<String> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(head.as_bytes()).await?;
+        let mut out = String::new();
+        stream.read_to_string(&mut out).await?;
+        Ok(out)
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("req.page.1")?;
+        metrics.register_counter("http{method=\"GET\"}")?;
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(
+            MetricsServer::new(Arc::new(metrics.clone()))
+                .help("req.page.1", "page 1 requests")
+                .serve_with_shutdown(listener, async {
+                    let _ = rx.await;
+                }),
+        );
+
+        let text = request(addr, "GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n").await?;
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(text.contains("Content-Type: text/plain; version=0.0.4"));
+        assert!(text.ends_with(
+            "# TYPE http counter\nhttp{method=\"GET\"} 0\n\
+             # HELP req_page_1 page 1 requests\n# TYPE req_page_1 gauge\nreq_page_1 1\n"
+        ));
+
+        let open = request(
+            addr,
+            "GET /metrics HTTP/1.1\r\nAccept: application/openmetrics-text\r\n\r\n",
+        )
+        .await?;
+        assert!(open.contains("http_total{method=\"GET\"} 0\n"));
+        assert!(open.ends_with("# EOF\n"));
+
+        metrics.inc("req.page.1")?;
+        let json = request(addr, "GET /metrics.json?pretty HTTP/1.1\r\n\r\n").await?;
+        assert!(json.contains("Content-Type: application/json"));
+        assert!(json.contains(r#""req.page.1":{"type":"gauge","value":2}"#));
+
+        let head = request(addr, "HEAD /metrics.json HTTP/1.1\r\n\r\n").await?;
+        assert!(head.starts_with("HTTP/1.1 200 OK") && head.ends_with("\r\n\r\n"));
+        let missing = request(addr, "GET /nope HTTP/1.1\r\n\r\n").await?;
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found"));
+        let post = request(addr, "POST /metrics HTTP/1.1\r\n\r\n").await?;
+        assert!(post.starts_with("HTTP/1.1 405"));
+
+        tx.send(()).map_err(|_| anyhow!("server already stopped"))?;
+        server.await??;
+        assert!(TcpStream::connect(addr).await.is_err());
+        Ok(())
+    }
+
+    async fn spawn_server(
+        server: MetricsServer,
+    ) -> Result<(SocketAddr, oneshot::Sender<()>, JoinHandle<Result<()>>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(server.serve_with_shutdown(listener, async {
+            let _ = rx.await;
+        }));
+        Ok((addr, tx, server))
+    }
+
+    #[tokio::test]
+    async fn test_slow_connections_dont_block_shutdown() -> Result<()> {
+        let server = MetricsServer::new(Arc::new(CmapMetrics::new()))
+            .request_timeout(Duration::from_millis(50));
+        let (addr, tx, server) = spawn_server(server).await?;
+        // 连上之后什么都不发, 超时之后服务端关掉连接
+        let mut idle = TcpStream::connect(addr).await?;
+        let mut out = Vec::new();
+        idle.read_to_end(&mut out).await?;
+        assert!(out.is_empty());
+        let _ = tx.send(());
+        server.await??;
+
+        // 请求头只发一半, 停机等过宽限期之后放弃这个连接
+        let server = MetricsServer::new(Arc::new(CmapMetrics::new()))
+            .request_timeout(Duration::from_secs(60))
+            .shutdown_grace(Duration::from_millis(50));
+        let (addr, tx, server) = spawn_server(server).await?;
+        let mut stuck = TcpStream::connect(addr).await?;
+        stuck.write_all(b"GET /metrics HTTP/1.1\r\n").await?;
+        let text = request(addr, "GET /metrics HTTP/1.1\r\n\r\n").await?;
+        assert!(text.starts_with("HTTP/1.1 200 OK"));
+        let _ = tx.send(());
+        timeout(Duration::from_secs(5), server).await???;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics_fn() -> Result<()> {
+        let metrics = AmapMetrics::new(&["a"]);
+        metrics.inc("a")?;
+        // 先绑定一个端口拿到地址, 再释放给 serve_metrics 用
+        let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+        let (tx, rx) = oneshot::channel::<()>();
+        let server = tokio::spawn(serve_metrics(addr, Arc::new(metrics), async {
+            let _ = rx.await;
+        }));
+        let mut text = Err(anyhow!("server never came up"));
+        for _ in 0..50 {
+            text = request(addr, "GET /metrics HTTP/1.1\r\n\r\n").await;
+            if text.is_ok() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert!(text?.ends_with("# TYPE a gauge\na 1\n"));
+        let _ = tx.send(());
+        server.await??;
+        Ok(())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use super::{labels::split_key, HistogramSnapshot, LabelPairs, MeterSnapshot, MetricKind};
+
+/// An owned copy of every metric at one point in time, sorted by key
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsSnapshot {
+    timestamp: SystemTime,
+    values: BTreeMap<String, MetricValue>,
+}
+
+/// The value of one metric in a snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Counter(i64),
+    Gauge(i64),
+    FloatGauge(f64),
+    Histogram(HistogramSnapshot),
+    Meter(MeterSnapshot),
+}
+
+/// What changed between two snapshots
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsDiff {
+    /// time between the two snapshots
+    pub elapsed: Duration,
+    /// `current - previous` for every key in either snapshot, a missing key counts as 0
+    pub deltas: BTreeMap<String, MetricValue>,
+}
+
+// region:    --- impls
+impl MetricsSnapshot {
+    /// A snapshot taken now
+    pub fn new(values: impl IntoIterator<Item = (String, MetricValue)>) -> Self {
+        Self::with_timestamp(SystemTime::now(), values)
+    }
+
+    pub fn with_timestamp(
+        timestamp: SystemTime,
+        values: impl IntoIterator<Item = (String, MetricValue)>,
+    ) -> Self {
+        Self {
+            timestamp,
+            values: values.into_iter().collect(),
+        }
+    }
+
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    pub fn get(&self, key: &str) -> Option<&MetricValue> {
+        self.values.get(key)
+    }
+
+    /// Every key and value, in key order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetricValue)> {
+        self.values.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn into_map(self) -> BTreeMap<String, MetricValue> {
+        self.values
+    }
+
+    /// Series grouped by metric name, a `name{k="v",...}` key is split into
+    /// its name and labels
+    pub fn families(&self) -> BTreeMap<&str, Vec<(LabelPairs, &MetricValue)>> {
+        let mut families: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (key, value) in self.iter() {
+            let (name, labels) = split_key(key);
+            families.entry(name).or_default().push((labels, value));
+        }
+        families
+    }
+
+    /// Deltas since `previous`, for reporters that print rates
+    ///
+    /// Histograms and meters that disappeared are left out, their counts can't go negative.
+    pub fn diff(&self, previous: &MetricsSnapshot) -> MetricsDiff {
+        let mut deltas = BTreeMap::new();
+        for (k, v) in &self.values {
+            let delta = match previous.values.get(k) {
+                Some(p) => v.sub(p),
+                None => v.clone(),
+            };
+            deltas.insert(k.clone(), delta);
+        }
+        for (k, p) in &previous.values {
+            if self.values.contains_key(k) {
+                continue;
+            }
+            if let Some(neg) = p.neg() {
+                deltas.insert(k.clone(), neg);
+            }
+        }
+        MetricsDiff {
+            elapsed: self
+                .timestamp
+                .duration_since(previous.timestamp)
+                .unwrap_or_default(),
+            deltas,
+        }
+    }
+}
+
+impl fmt::Display for MetricsSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in self.iter() {
+            writeln!(f, "{}: {}", key, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl MetricValue {
+    pub fn kind(&self) -> MetricKind {
+        match self {
+            MetricValue::Counter(_) => MetricKind::Counter,
+            MetricValue::Gauge(_) => MetricKind::Gauge,
+            MetricValue::FloatGauge(_) => MetricKind::FloatGauge,
+            MetricValue::Histogram(_) => MetricKind::Histogram,
+            MetricValue::Meter(_) => MetricKind::Meter,
+        }
+    }
+
+    /// Counters and gauges
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            MetricValue::Counter(v) | MetricValue::Gauge(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    /// The value as a number, the observation count for histograms and meters
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            MetricValue::Counter(v) | MetricValue::Gauge(v) => *v as f64,
+            MetricValue::FloatGauge(v) => *v,
+            MetricValue::Histogram(h) => h.count() as f64,
+            MetricValue::Meter(m) => m.count as f64,
+        }
+    }
+
+    // 类型不一致时 (key 被重新注册成别的类型) 直接取当前值
+    fn sub(&self, previous: &MetricValue) -> MetricValue {
+        match (self, previous) {
+            (MetricValue::Counter(a), MetricValue::Counter(b)) => MetricValue::Counter(a - b),
+            (MetricValue::Gauge(a), MetricValue::Gauge(b)) => MetricValue::Gauge(a - b),
+            (MetricValue::FloatGauge(a), MetricValue::FloatGauge(b)) => {
+                MetricValue::FloatGauge(a - b)
+            }
+            (MetricValue::Histogram(a), MetricValue::Histogram(b)) if a.bounds == b.bounds => {
+                MetricValue::Histogram(HistogramSnapshot {
+                    bounds: a.bounds.clone(),
+                    counts: a
+                        .counts
+                        .iter()
+                        .zip(&b.counts)
+                        .map(|(x, y)| x.saturating_sub(*y))
+                        .collect(),
+                    sum: a.sum - b.sum,
+                })
+            }
+            // 速率本身就是窗口内的值, 只有总数需要相减
+            (MetricValue::Meter(a), MetricValue::Meter(b)) => MetricValue::Meter(MeterSnapshot {
+                count: a.count.saturating_sub(b.count),
+                ..*a
+            }),
+            _ => self.clone(),
+        }
+    }
+
+    fn neg(&self) -> Option<MetricValue> {
+        match self {
+            MetricValue::Counter(v) => Some(MetricValue::Counter(-v)),
+            MetricValue::Gauge(v) => Some(MetricValue::Gauge(-v)),
+            MetricValue::FloatGauge(v) => Some(MetricValue::FloatGauge(-v)),
+            MetricValue::Histogram(_) | MetricValue::Meter(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for MetricValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricValue::Counter(v) | MetricValue::Gauge(v) => write!(f, "{}", v),
+            MetricValue::FloatGauge(v) => write!(f, "{}", v),
+            MetricValue::Histogram(h) => write!(f, "count={} sum={}", h.count(), h.sum),
+            MetricValue::Meter(m) => write!(
+                f,
+                "count={} 1m={:.3} 5m={:.3} 15m={:.3}",
+                m.count, m.ewma[0], m.ewma[1], m.ewma[2]
+            ),
+        }
+    }
+}
+
+impl MetricsDiff {
+    /// Change per second of `key`, `None` if it's unknown or no time passed
+    pub fn per_second(&self, key: &str) -> Option<f64> {
+        let delta = self.deltas.get(key)?.as_f64();
+        let secs = self.elapsed.as_secs_f64();
+        (secs > 0.0).then(|| delta / secs)
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_is_sorted() {
+        let s = MetricsSnapshot::new([
+            ("b".to_string(), MetricValue::Gauge(2)),
+            ("a".to_string(), MetricValue::Counter(1)),
+        ]);
+        let keys = s.iter().map(|(k, _)| k).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "b"]);
+        assert_eq!(s.to_string(), "a: 1\nb: 2\n");
+    }
+
+    #[test]
+    fn test_families() {
+        let s = MetricsSnapshot::new([
+            (r#"req{page="1"}"#.to_string(), MetricValue::Counter(1)),
+            (r#"req{page="2"}"#.to_string(), MetricValue::Counter(2)),
+            ("up".to_string(), MetricValue::Gauge(1)),
+        ]);
+        let families = s.families();
+        assert_eq!(families.keys().collect::<Vec<_>>(), vec![&"req", &"up"]);
+        assert_eq!(
+            families["req"][1],
+            (
+                vec![("page".to_string(), "2".to_string())],
+                &MetricValue::Counter(2)
+            )
+        );
+    }
+
+    #[test]
+    fn test_snapshot_diff() {
+        let t0 = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+        let previous = MetricsSnapshot::with_timestamp(
+            t0,
+            [
+                ("a".to_string(), MetricValue::Counter(5)),
+                ("gone".to_string(), MetricValue::Gauge(3)),
+            ],
+        );
+        let current = MetricsSnapshot::with_timestamp(
+            t0 + Duration::from_secs(2),
+            [
+                ("a".to_string(), MetricValue::Counter(9)),
+                ("new".to_string(), MetricValue::FloatGauge(4.0)),
+            ],
+        );
+        let diff = current.diff(&previous);
+        assert_eq!(diff.elapsed, Duration::from_secs(2));
+        assert_eq!(
+            diff.deltas.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("a".to_string(), MetricValue::Counter(4)),
+                ("gone".to_string(), MetricValue::Gauge(-3)),
+                ("new".to_string(), MetricValue::FloatGauge(4.0))
+            ]
+        );
+        assert_eq!(current.diff(&previous).per_second("a"), Some(2.0));
+        assert_eq!(previous.diff(&current).elapsed, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_histogram_diff() {
+        let h = |counts: Vec<u64>, sum| {
+            MetricValue::Histogram(HistogramSnapshot {
+                bounds: vec![1.0],
+                counts,
+                sum,
+            })
+        };
+        let previous = MetricsSnapshot::new([("h".to_string(), h(vec![1, 2], 5.0))]);
+        let current = MetricsSnapshot::new([("h".to_string(), h(vec![4, 2], 6.5))]);
+        assert_eq!(current.diff(&previous).deltas["h"], h(vec![3, 0], 1.5));
+        assert!(MetricsSnapshot::new([]).diff(&current).deltas.is_empty());
+    }
+}
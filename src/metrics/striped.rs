@@ -0,0 +1,149 @@
+use std::{
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc, OnceLock,
+    },
+    thread,
+};
+
+use crossbeam_utils::CachePadded;
+
+// 分片数的上限, 再多读的时候求和就太慢了
+const MAX_SHARDS: usize = 64;
+
+/// A counter split into cache-line padded shards, one picked per thread
+///
+/// Writers on different threads usually touch different cache lines, so
+/// heavily contended increments don't ping-pong one line between cores.
+/// Reads sum every shard, so they cost more than a plain atomic load and
+/// aren't a single atomic snapshot while writers are running.
+#[derive(Debug, Clone)]
+pub struct StripedCounter {
+    shards: Arc<[CachePadded<AtomicI64>]>,
+}
+
+// region:    --- impls
+impl StripedCounter {
+    /// One shard per CPU, rounded up to a power of two
+    pub fn new() -> Self {
+        static DEFAULT_SHARDS: OnceLock<usize> = OnceLock::new();
+        let shards = *DEFAULT_SHARDS.get_or_init(|| {
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        Self::with_shards(shards)
+    }
+
+    /// `shards` is rounded up to a power of two, at most 64
+    pub fn with_shards(shards: usize) -> Self {
+        let shards = shards.clamp(1, MAX_SHARDS).next_power_of_two();
+        Self {
+            shards: (0..shards)
+                .map(|_| CachePadded::new(AtomicI64::new(0)))
+                .collect(),
+        }
+    }
+
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    /// Saturates at `i64::MIN`/`i64::MAX` instead of wrapping
+    pub fn add(&self, delta: i64) {
+        saturating_add(&self.shards[shard_index() & (self.shards.len() - 1)], delta);
+    }
+
+    /// Sum of every shard, clamped to the `i64` range
+    pub fn get(&self) -> i64 {
+        // 每个分片都可能接近 i64::MAX, 用 i128 求和再截断, 不会溢出
+        let sum: i128 = self
+            .shards
+            .iter()
+            .map(|s| i128::from(s.load(Ordering::Relaxed)))
+            .sum();
+        sum.clamp(i64::MIN.into(), i64::MAX.into()) as i64
+    }
+
+    pub(crate) fn reset(&self) {
+        for shard in self.shards.iter() {
+            shard.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for StripedCounter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// endregion: --- impls
+
+// fetch_add 溢出会回绕, 用 CAS 循环在两端饱和
+pub(crate) fn saturating_add(cell: &AtomicI64, delta: i64) {
+    let _ = cell.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |v| {
+        Some(v.saturating_add(delta))
+    });
+}
+
+// 每个线程第一次用的时候按顺序领一个编号, 相邻的线程落在不同的分片上
+fn shard_index() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static INDEX: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    INDEX.with(|i| *i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_striped_counter_sums_shards() {
+        let counter = StripedCounter::with_shards(6);
+        assert_eq!(counter.shards(), 8);
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        counter.inc();
+                    }
+                    counter.add(-5);
+                });
+            }
+        });
+        assert_eq!(counter.get(), 8 * (10_000 - 5));
+        // 每个分片独占一条 cache line
+        assert!(std::mem::size_of::<CachePadded<AtomicI64>>() >= 64);
+        counter.reset();
+        assert_eq!(counter.get(), 0);
+        assert_eq!(StripedCounter::with_shards(1000).shards(), MAX_SHARDS);
+    }
+
+    #[test]
+    fn test_striped_counter_saturates() {
+        let counter = StripedCounter::with_shards(2);
+        // 每个线程落在哪个分片取决于编号; 同一个分片时 add 饱和, 不同分片时求和饱和
+        for delta in [i64::MAX, i64::MAX, 1] {
+            let counter = &counter;
+            thread::scope(|s| {
+                s.spawn(move || counter.add(delta));
+            });
+        }
+        assert_eq!(counter.get(), i64::MAX);
+
+        let counter = StripedCounter::with_shards(1);
+        counter.add(i64::MAX);
+        counter.add(i64::MAX);
+        assert_eq!(counter.get(), i64::MAX);
+        counter.reset();
+        counter.add(i64::MIN);
+        counter.add(-1);
+        assert_eq!(counter.get(), i64::MIN);
+    }
+}
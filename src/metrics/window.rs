@@ -0,0 +1,352 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+
+// 每个 bucket 是一个 AtomicU64: 高 32 位是 bucket 的序号 (epoch), 低 32 位是计数
+const EPOCH_SHIFT: u32 = 32;
+const COUNT_MASK: u64 = (1 << EPOCH_SHIFT) - 1;
+
+const WINDOW_BUCKET: Duration = Duration::from_secs(1);
+const WINDOW_BUCKETS: usize = 15 * 60;
+// 和 Unix load average 一样, 每 5 秒更新一次 EWMA
+const EWMA_TICK: Duration = Duration::from_secs(5);
+const EWMA_MINUTES: [f64; 3] = [1.0, 5.0, 15.0];
+
+/// Where windowed metrics read the time from
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Monotonic time since some fixed origin
+    fn now(&self) -> Duration;
+}
+
+/// The real monotonic clock, every `SystemClock` shares one origin
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+/// A clock that only moves when told to, for deterministic tests
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+/// Counts events in a ring of time buckets to answer "how many in the last N"
+///
+/// Adding and reading are lock-free: each bucket packs its epoch and count
+/// into one atomic, a writer that finds a stale epoch restarts the bucket.
+/// A bucket holds at most `u32::MAX` events, more saturate.
+#[derive(Debug, Clone)]
+pub struct WindowedCounter {
+    clock: Arc<dyn Clock>,
+    bucket: Duration,
+    buckets: Arc<[AtomicU64]>,
+}
+
+/// Total count plus 1/5/15-minute windowed and EWMA rates, per second
+#[derive(Debug, Clone)]
+pub struct Meter(Arc<MeterCore>);
+
+#[derive(Debug)]
+struct MeterCore {
+    total: AtomicU64,
+    window: WindowedCounter,
+    ewma: Ewma,
+}
+
+// 三个 EWMA 共用一份未结算的计数和最后一次 tick 的时刻
+#[derive(Debug)]
+struct Ewma {
+    uncounted: AtomicU64,
+    last_tick: AtomicU64,
+    /// f64 bits, events per second
+    rates: [AtomicU64; 3],
+}
+
+/// A meter at one point in time, rates are events per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MeterSnapshot {
+    pub count: u64,
+    /// exact counts over the last 1, 5 and 15 minutes, divided by the window
+    pub rates: [f64; 3],
+    /// exponentially weighted 1, 5 and 15 minute averages
+    pub ewma: [f64; 3],
+}
+
+// region:    --- impls
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        static ORIGIN: OnceLock<Instant> = OnceLock::new();
+        ORIGIN.get_or_init(Instant::now).elapsed()
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn set(&self, now: Duration) {
+        self.0.store(now.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+}
+
+impl WindowedCounter {
+    /// `buckets` of width `bucket` each, so windows up to `bucket * buckets`
+    pub fn new(clock: Arc<dyn Clock>, bucket: Duration, buckets: usize) -> Result<Self> {
+        if bucket.is_zero() || buckets == 0 {
+            return Err(anyhow!(
+                "WindowedCounter error: bucket width and count must be positive"
+            ));
+        }
+        Ok(Self {
+            clock,
+            bucket,
+            buckets: (0..buckets).map(|_| AtomicU64::new(0)).collect(),
+        })
+    }
+
+    /// The longest window this counter can answer for
+    pub fn span(&self) -> Duration {
+        self.bucket * self.buckets.len() as u32
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        let epoch = self.epoch();
+        let n = n.min(COUNT_MASK);
+        let bucket = &self.buckets[(epoch % self.buckets.len() as u64) as usize];
+        let _ = bucket.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |packed| {
+            let count = if packed >> EPOCH_SHIFT == epoch {
+                ((packed & COUNT_MASK) + n).min(COUNT_MASK)
+            } else {
+                // 上一轮留下来的旧 bucket, 从头开始计
+                n
+            };
+            Some(epoch << EPOCH_SHIFT | count)
+        });
+    }
+
+    /// Events in the last `window`, rounded up to whole buckets and capped at `span`
+    pub fn count(&self, window: Duration) -> u64 {
+        let epoch = self.epoch();
+        let wanted = (window.as_nanos().div_ceil(self.bucket.as_nanos()) as u64)
+            .min(self.buckets.len() as u64);
+        self.buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .filter(|packed| {
+                let age = epoch.wrapping_sub(packed >> EPOCH_SHIFT) & COUNT_MASK;
+                age < wanted
+            })
+            .map(|packed| packed & COUNT_MASK)
+            .sum()
+    }
+
+    /// Events per second over the last `window`
+    pub fn rate(&self, window: Duration) -> f64 {
+        let window = window.min(self.span());
+        if window.is_zero() {
+            return 0.0;
+        }
+        self.count(window) as f64 / window.as_secs_f64()
+    }
+
+    fn epoch(&self) -> u64 {
+        (self.clock.now().as_nanos() / self.bucket.as_nanos()) as u64 & COUNT_MASK
+    }
+
+    fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Meter {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.now();
+        let window = WindowedCounter::new(clock, WINDOW_BUCKET, WINDOW_BUCKETS)
+            .expect("default window is valid");
+        Self(Arc::new(MeterCore {
+            total: AtomicU64::new(0),
+            window,
+            ewma: Ewma::new(ewma_ticks(now)),
+        }))
+    }
+
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        let core = &self.0;
+        core.ewma.tick(ewma_ticks(core.window.clock.now()));
+        core.total.fetch_add(n, Ordering::Relaxed);
+        core.window.add(n);
+        core.ewma.uncounted.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.total.load(Ordering::Relaxed)
+    }
+
+    pub fn snapshot(&self) -> MeterSnapshot {
+        let core = &self.0;
+        core.ewma.tick(ewma_ticks(core.window.clock.now()));
+        MeterSnapshot {
+            count: self.count(),
+            rates: EWMA_MINUTES.map(|m| core.window.rate(Duration::from_secs_f64(m * 60.0))),
+            ewma: core
+                .ewma
+                .rates
+                .each_ref()
+                .map(|r| f64::from_bits(r.load(Ordering::Relaxed))),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        let core = &self.0;
+        core.total.store(0, Ordering::Relaxed);
+        core.window.reset();
+        core.ewma.uncounted.store(0, Ordering::Relaxed);
+        for rate in &core.ewma.rates {
+            rate.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl Ewma {
+    fn new(tick: u64) -> Self {
+        Self {
+            uncounted: AtomicU64::new(0),
+            last_tick: AtomicU64::new(tick),
+            rates: Default::default(),
+        }
+    }
+
+    // 谁把 last_tick 推进了谁负责结算; 中间空着的 tick 只衰减
+    fn tick(&self, now: u64) {
+        let last = self.last_tick.load(Ordering::Relaxed);
+        if now <= last
+            || self
+                .last_tick
+                .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+        // 空闲很久之后衰减到 0 就够了, 不用算到底
+        let elapsed = (now - last).min(u16::MAX as u64) as i32;
+        let instant = self.uncounted.swap(0, Ordering::AcqRel) as f64 / EWMA_TICK.as_secs_f64();
+        for (rate, minutes) in self.rates.iter().zip(EWMA_MINUTES) {
+            let keep = (-EWMA_TICK.as_secs_f64() / (minutes * 60.0)).exp();
+            let _ = rate.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let r = f64::from_bits(bits);
+                let r = instant + keep * (r - instant);
+                Some((r * keep.powi(elapsed - 1)).to_bits())
+            });
+        }
+    }
+}
+// endregion: --- impls
+
+fn ewma_ticks(now: Duration) -> u64 {
+    (now.as_nanos() / EWMA_TICK.as_nanos()) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn test_windowed_counter() -> Result<()> {
+        let clock = ManualClock::new();
+        let counter = WindowedCounter::new(Arc::new(clock.clone()), WINDOW_BUCKET, 60)?;
+        for _ in 0..30 {
+            counter.add(2);
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(counter.count(Duration::from_secs(60)), 60);
+        assert_eq!(counter.count(Duration::from_secs(10)), 18);
+        assert_eq!(counter.rate(Duration::from_secs(60)), 1.0);
+
+        // 转过一整圈之后, 旧的 bucket 不再算进来
+        // 现在是第 75 秒, 最近 60 秒里只剩第 16..29 秒
+        clock.advance(Duration::from_secs(45));
+        assert_eq!(counter.count(Duration::from_secs(60)), 28);
+        counter.inc();
+        assert_eq!(counter.count(Duration::from_secs(1)), 1);
+        clock.advance(Duration::from_secs(600));
+        assert_eq!(counter.count(Duration::from_secs(3600)), 0);
+        assert!(WindowedCounter::new(Arc::new(SystemClock), Duration::ZERO, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_windowed_counter_concurrent_adds() -> Result<()> {
+        let counter = WindowedCounter::new(Arc::new(ManualClock::new()), WINDOW_BUCKET, 4)?;
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        counter.inc();
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.count(Duration::from_secs(1)), 4000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_meter_rates() {
+        let clock = ManualClock::new();
+        let meter = Meter::new(Arc::new(clock.clone()));
+        // 10 分钟, 每秒 4 个
+        for _ in 0..600 {
+            clock.advance(Duration::from_secs(1));
+            meter.add(4);
+        }
+        let s = meter.snapshot();
+        assert_eq!(s.count, 2400);
+        assert_eq!(s.rates[0], 4.0);
+        assert_eq!(s.rates[1], 4.0);
+        assert_eq!(s.rates[2], 2400.0 / 900.0);
+        // 1 分钟的 EWMA 已经收敛, 15 分钟的还在往上走
+        assert!((s.ewma[0] - 4.0).abs() < 0.01, "{:?}", s.ewma);
+        assert!(s.ewma[1] > s.ewma[2] && s.ewma[2] > 1.0 && s.ewma[2] < 4.0);
+
+        // 停下来之后只衰减
+        clock.advance(Duration::from_secs(300));
+        let idle = meter.snapshot();
+        assert_eq!(idle.rates[0], 0.0);
+        assert!(idle.ewma[0] < 0.03 && idle.ewma[2] < s.ewma[2]);
+
+        meter.reset();
+        assert_eq!(meter.snapshot().count, 0);
+    }
+}
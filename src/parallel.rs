@@ -0,0 +1,57 @@
+use std::{
+    ops::{Add, Range},
+    thread,
+};
+
+/// Below this many touched elements the sequential path wins over spawning threads
+pub(crate) const PAR_THRESHOLD: usize = 1 << 15;
+
+pub(crate) fn num_threads() -> usize {
+    thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+/// Split `0..len` into contiguous ranges and run `f` on each range in its own
+/// scoped thread. `weight` is the number of elements touched per index and is
+/// checked against `PAR_THRESHOLD`. Results come back in range order.
+pub(crate) fn par_ranges<R, F>(len: usize, weight: usize, f: F) -> Vec<R>
+where
+    R: Send,
+    F: Fn(Range<usize>) -> R + Sync,
+{
+    let threads = num_threads().min(len);
+    if threads <= 1 || len.saturating_mul(weight) < PAR_THRESHOLD {
+        return vec![f(0..len)];
+    }
+    let chunk = len.div_ceil(threads);
+    // scoped thread 可以直接借用 data, 不需要 Arc 或者 'static
+    thread::scope(|s| {
+        let handles = (0..len)
+            .step_by(chunk)
+            .map(|start| {
+                let f = &f;
+                s.spawn(move || f(start..(start + chunk).min(len)))
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("parallel worker panicked"))
+            .collect()
+    })
+}
+
+/// Run `f` over contiguous chunks of `data`, in parallel for large inputs
+pub(crate) fn par_chunks<T, R, F>(data: &[T], f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&[T]) -> R + Sync,
+{
+    par_ranges(data.len(), 1, |range| f(&data[range]))
+}
+
+/// Sequential sum of one chunk, the per-thread step of the parallel reductions
+pub(crate) fn sum_of<T: Default + Add<Output = T>>(iter: impl Iterator<Item = T>) -> T {
+    iter.fold(T::default(), |acc, x| acc + x)
+}
@@ -0,0 +1,219 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+/// How a `MapReduce` job hands its inputs to the worker threads
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Scheduler {
+    /// every input goes to the worker picked by the partitioner
+    #[default]
+    Static,
+    /// inputs go to a global injector, idle workers steal from busy ones;
+    /// the partitioner is not used
+    WorkStealing,
+}
+
+/// What the workers did during one job
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SchedulerStats {
+    /// number of tasks run by each worker
+    pub tasks: Vec<usize>,
+    /// successful steals from another worker's deque
+    pub steals: usize,
+    /// total time workers sat idle, out of work, while the job was still running
+    pub idle: Duration,
+    /// wall time of the whole job
+    pub elapsed: Duration,
+}
+
+/// A work-stealing pool: one deque per worker plus a global injector
+///
+/// A worker pops its own deque first, then takes a batch from the injector,
+/// then steals a batch from the other workers.
+#[derive(Debug, Clone, Copy)]
+pub struct WorkStealingPool {
+    num_threads: usize,
+}
+
+// 每个 worker 线程自己的统计, 结束时汇总到 SchedulerStats
+#[derive(Default)]
+struct WorkerStats {
+    tasks: usize,
+    steals: usize,
+    finished: Duration,
+}
+
+// region:    --- impls
+impl WorkStealingPool {
+    pub fn new(num_threads: usize) -> Self {
+        Self {
+            num_threads: num_threads.max(1),
+        }
+    }
+
+    /// Run `f` on every task, outputs come back in task order
+    ///
+    /// A panic in `f` is returned as an error once every worker has stopped.
+    pub fn run<I, O, F>(
+        &self,
+        tasks: impl IntoIterator<Item = I>,
+        f: F,
+    ) -> Result<(Vec<O>, SchedulerStats)>
+    where
+        I: Send,
+        O: Send,
+        F: Fn(I) -> O + Sync,
+    {
+        let injector = Injector::new();
+        let mut len = 0;
+        for task in tasks {
+            injector.push((len, task));
+            len += 1;
+        }
+        let workers = (0..self.num_threads)
+            .map(|_| Worker::new_fifo())
+            .collect::<Vec<_>>();
+        let stealers = workers.iter().map(Worker::stealer).collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let finished = thread::scope(|s| {
+            let handles = workers
+                .into_iter()
+                .enumerate()
+                .map(|(me, local)| {
+                    let (injector, stealers, f) = (&injector, &stealers, &f);
+                    s.spawn(move || {
+                        let mut outputs = Vec::new();
+                        let mut stats = WorkerStats::default();
+                        while let Some((seq, task)) =
+                            find_task(me, &local, injector, stealers, &mut stats)
+                        {
+                            outputs.push((seq, f(task)));
+                            stats.tasks += 1;
+                        }
+                        stats.finished = start.elapsed();
+                        (outputs, stats)
+                    })
+                })
+                .collect::<Vec<_>>();
+            // 先 join 所有线程再看结果, scope 不会因为某个 worker panic 而 panic
+            handles.into_iter().map(|h| h.join()).collect::<Vec<_>>()
+        });
+        let elapsed = start.elapsed();
+        let finished = finished
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| anyhow!("WorkStealingPool error: worker panicked"))?;
+
+        let mut slots = (0..len).map(|_| None).collect::<Vec<_>>();
+        let mut stats = SchedulerStats {
+            elapsed,
+            ..Default::default()
+        };
+        for (outputs, worker) in finished {
+            for (seq, output) in outputs {
+                slots[seq] = Some(output);
+            }
+            stats.tasks.push(worker.tasks);
+            stats.steals += worker.steals;
+            stats.idle += elapsed.saturating_sub(worker.finished);
+        }
+        let outputs = slots
+            .into_iter()
+            .map(|o| o.expect("every task runs exactly once"))
+            .collect();
+        Ok((outputs, stats))
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+// 所有任务在 worker 启动前就已经放进 injector, 所以到处都找不到任务时就可以退出
+fn find_task<T>(
+    me: usize,
+    local: &Worker<T>,
+    injector: &Injector<T>,
+    stealers: &[Stealer<T>],
+    stats: &mut WorkerStats,
+) -> Option<T> {
+    if let Some(task) = local.pop() {
+        return Some(task);
+    }
+    loop {
+        let mut retry = false;
+        match injector.steal_batch_and_pop(local) {
+            Steal::Success(task) => return Some(task),
+            Steal::Retry => retry = true,
+            Steal::Empty => {}
+        }
+        for (idx, stealer) in stealers.iter().enumerate() {
+            if idx == me {
+                continue;
+            }
+            match stealer.steal_batch_and_pop(local) {
+                Steal::Success(task) => {
+                    stats.steals += 1;
+                    return Some(task);
+                }
+                Steal::Retry => retry = true,
+                Steal::Empty => {}
+            }
+        }
+        if !retry {
+            return None;
+        }
+    }
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_work_stealing_pool_runs_every_task_in_order() -> Result<()> {
+        let pool = WorkStealingPool::new(4);
+        let (outputs, stats) = pool.run(0..1000u64, |x| x * 2)?;
+        assert_eq!(outputs, (0..1000u64).map(|x| x * 2).collect::<Vec<_>>());
+        assert_eq!(stats.tasks.len(), 4);
+        assert_eq!(stats.tasks.iter().sum::<usize>(), 1000);
+        Ok(())
+    }
+
+    #[test]
+    fn test_work_stealing_pool_balances_uneven_tasks() -> Result<()> {
+        // 第一个 batch 里的任务很重, 其余 worker 应该会来偷
+        let pool = WorkStealingPool::new(4);
+        let costs = (0..64).map(|i| if i < 8 { 20 } else { 0 });
+        let (outputs, stats) = pool.run(costs, |ms| {
+            thread::sleep(Duration::from_millis(ms));
+            ms
+        })?;
+        assert_eq!(outputs.len(), 64);
+        assert_eq!(stats.tasks.iter().sum::<usize>(), 64);
+        assert!(stats.steals > 0);
+        assert!(stats.tasks.iter().filter(|&&n| n > 0).count() > 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_work_stealing_pool_empty() -> Result<()> {
+        let (outputs, stats) = WorkStealingPool::new(2).run(Vec::<u8>::new(), |x| x)?;
+        assert!(outputs.is_empty());
+        assert_eq!(stats.tasks, vec![0, 0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_work_stealing_pool_worker_panic() {
+        let ret = WorkStealingPool::new(2).run(0..10, |x| {
+            assert!(x != 7, "task {} failed", x);
+            x
+        });
+        assert!(ret.is_err());
+    }
+}
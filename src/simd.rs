@@ -0,0 +1,399 @@
+// 为 f32/f64/i32/i64 提供向量化的点积, 其它类型走通用的标量实现
+//
+// Rust 还没有稳定的 specialization, 所以在运行时按类型分派:
+// 类型相同的时候, 把 &[T] 当成 &[f32] 等具体类型来用是安全的.
+// 不用 TypeId, 它要求 T: 'static, 会把这个约束带到公开的 dot_product 上 (见 is_primitive).
+// x86_64 上运行时检测 AVX2, 否则使用 x86_64 基线就有的 SSE2.
+// i32/i64 在所有路径上 (SIMD, 标量, 分块求和) 溢出时都按 wrapping 语义处理.
+
+use std::{
+    any::type_name,
+    mem::{align_of, size_of},
+    ops::{AddAssign, Mul},
+    slice,
+};
+
+/// Dot product of two slices of the same length
+pub(crate) fn dot<T>(a: &[T], b: &[T]) -> T
+where
+    T: Copy + Default + AddAssign + Mul<Output = T>,
+{
+    debug_assert_eq!(a.len(), b.len());
+    if let (Some(a), Some(b)) = (cast::<T, f32>(a), cast::<T, f32>(b)) {
+        return uncast(dot_f32(a, b));
+    }
+    if let (Some(a), Some(b)) = (cast::<T, f64>(a), cast::<T, f64>(b)) {
+        return uncast(dot_f64(a, b));
+    }
+    if let (Some(a), Some(b)) = (cast::<T, i32>(a), cast::<T, i32>(b)) {
+        return uncast(dot_i32(a, b));
+    }
+    if let (Some(a), Some(b)) = (cast::<T, i64>(a), cast::<T, i64>(b)) {
+        return uncast(dot_i64(a, b));
+    }
+    dot_scalar(a, b)
+}
+
+/// Portable fallback, four independent accumulators so the compiler can
+/// vectorise it on its own
+pub(crate) fn dot_scalar<T>(a: &[T], b: &[T]) -> T
+where
+    T: Copy + Default + AddAssign + Mul<Output = T>,
+{
+    let mut lanes = [T::default(); 4];
+    let (a4, b4) = (a.chunks_exact(4), b.chunks_exact(4));
+    let (ta, tb) = (a4.remainder(), b4.remainder());
+    for (x, y) in a4.zip(b4) {
+        for k in 0..4 {
+            lanes[k] += x[k] * y[k];
+        }
+    }
+    let mut sum = T::default();
+    for lane in lanes {
+        sum += lane;
+    }
+    for (&x, &y) in ta.iter().zip(tb) {
+        sum += x * y;
+    }
+    sum
+}
+
+/// `a + b`, wrapping for i32 and i64 like `dot`, for adding up partial dot products
+pub(crate) fn add<T: Copy + AddAssign>(mut a: T, b: T) -> T {
+    let (x, y) = (slice::from_ref(&a), slice::from_ref(&b));
+    if let (Some(x), Some(y)) = (cast::<T, i32>(x), cast::<T, i32>(y)) {
+        return uncast(x[0].wrapping_add(y[0]));
+    }
+    if let (Some(x), Some(y)) = (cast::<T, i64>(x), cast::<T, i64>(y)) {
+        return uncast(x[0].wrapping_add(y[0]));
+    }
+    a += b;
+    a
+}
+
+// U 只会是 f32/f64/i32/i64. 原生类型的 type_name 就是它自己的名字, 别的类型
+// (哪怕也叫 f32) 都带着模块路径, 引用之类的也不一样; 再核对一遍大小和对齐
+fn is_primitive<T, U: 'static>() -> bool {
+    type_name::<T>() == type_name::<U>()
+        && size_of::<T>() == size_of::<U>()
+        && align_of::<T>() == align_of::<U>()
+}
+
+fn cast<T, U: 'static>(s: &[T]) -> Option<&[U]> {
+    if is_primitive::<T, U>() {
+        // SAFETY: T is the primitive U
+        Some(unsafe { slice::from_raw_parts(s.as_ptr() as *const U, s.len()) })
+    } else {
+        None
+    }
+}
+
+fn uncast<U: 'static, T>(value: U) -> T {
+    assert!(is_primitive::<T, U>());
+    // SAFETY: T is the primitive U
+    unsafe { std::mem::transmute_copy(&value) }
+}
+
+// region:    --- dispatch
+macro_rules! dispatch {
+    ($name:ident, $t:ty, $avx2:ident, $sse2:ident, $portable:expr) => {
+        #[cfg(target_arch = "x86_64")]
+        fn $name(a: &[$t], b: &[$t]) -> $t {
+            if is_x86_feature_detected!("avx2") {
+                // SAFETY: AVX2 is available on this CPU
+                unsafe { x86::$avx2(a, b) }
+            } else {
+                // SAFETY: SSE2 is part of the x86_64 baseline
+                unsafe { x86::$sse2(a, b) }
+            }
+        }
+
+        #[cfg(not(target_arch = "x86_64"))]
+        fn $name(a: &[$t], b: &[$t]) -> $t {
+            $portable(a, b)
+        }
+    };
+}
+
+dispatch!(dot_f32, f32, dot_f32_avx2, dot_f32_sse2, dot_scalar);
+dispatch!(dot_f64, f64, dot_f64_avx2, dot_f64_sse2, dot_scalar);
+dispatch!(dot_i32, i32, dot_i32_avx2, dot_i32_sse2, portable::dot_i32);
+dispatch!(dot_i64, i64, dot_i64_avx2, dot_i64_sse2, portable::dot_i64);
+// endregion: --- dispatch
+
+mod portable {
+    pub(super) fn dot_i32(a: &[i32], b: &[i32]) -> i32 {
+        a.iter()
+            .zip(b)
+            .fold(0i32, |acc, (&x, &y)| acc.wrapping_add(x.wrapping_mul(y)))
+    }
+
+    pub(super) fn dot_i64(a: &[i64], b: &[i64]) -> i64 {
+        a.iter()
+            .zip(b)
+            .fold(0i64, |acc, (&x, &y)| acc.wrapping_add(x.wrapping_mul(y)))
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::portable;
+
+    // region:    --- f32
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn dot_f32_avx2(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len()) / 8 * 8;
+        let mut acc = _mm256_setzero_ps();
+        for i in (0..n).step_by(8) {
+            let x = _mm256_loadu_ps(a.as_ptr().add(i));
+            let y = _mm256_loadu_ps(b.as_ptr().add(i));
+            acc = _mm256_add_ps(acc, _mm256_mul_ps(x, y));
+        }
+        let mut lanes = [0f32; 8];
+        _mm256_storeu_ps(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f32>() + tail(&a[n..], &b[n..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn dot_f32_sse2(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len()) / 4 * 4;
+        let mut acc = _mm_setzero_ps();
+        for i in (0..n).step_by(4) {
+            let x = _mm_loadu_ps(a.as_ptr().add(i));
+            let y = _mm_loadu_ps(b.as_ptr().add(i));
+            acc = _mm_add_ps(acc, _mm_mul_ps(x, y));
+        }
+        let mut lanes = [0f32; 4];
+        _mm_storeu_ps(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f32>() + tail(&a[n..], &b[n..])
+    }
+    // endregion: --- f32
+
+    // region:    --- f64
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn dot_f64_avx2(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len().min(b.len()) / 4 * 4;
+        let mut acc = _mm256_setzero_pd();
+        for i in (0..n).step_by(4) {
+            let x = _mm256_loadu_pd(a.as_ptr().add(i));
+            let y = _mm256_loadu_pd(b.as_ptr().add(i));
+            acc = _mm256_add_pd(acc, _mm256_mul_pd(x, y));
+        }
+        let mut lanes = [0f64; 4];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f64>() + tail(&a[n..], &b[n..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn dot_f64_sse2(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len().min(b.len()) / 2 * 2;
+        let mut acc = _mm_setzero_pd();
+        for i in (0..n).step_by(2) {
+            let x = _mm_loadu_pd(a.as_ptr().add(i));
+            let y = _mm_loadu_pd(b.as_ptr().add(i));
+            acc = _mm_add_pd(acc, _mm_mul_pd(x, y));
+        }
+        let mut lanes = [0f64; 2];
+        _mm_storeu_pd(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f64>() + tail(&a[n..], &b[n..])
+    }
+    // endregion: --- f64
+
+    // region:    --- i32
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn dot_i32_avx2(a: &[i32], b: &[i32]) -> i32 {
+        let n = a.len().min(b.len()) / 8 * 8;
+        let mut acc = _mm256_setzero_si256();
+        for i in (0..n).step_by(8) {
+            let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+            let y = _mm256_loadu_si256(b.as_ptr().add(i) as *const __m256i);
+            acc = _mm256_add_epi32(acc, _mm256_mullo_epi32(x, y));
+        }
+        let mut lanes = [0i32; 8];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+        lanes
+            .iter()
+            .fold(portable::dot_i32(&a[n..], &b[n..]), |s, &x| {
+                s.wrapping_add(x)
+            })
+    }
+
+    // SSE2 没有 32 位的 mullo, 用两次 32x32->64 的乘法取低 32 位
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn dot_i32_sse2(a: &[i32], b: &[i32]) -> i32 {
+        let n = a.len().min(b.len()) / 4 * 4;
+        let mut acc = _mm_setzero_si128();
+        for i in (0..n).step_by(4) {
+            let x = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+            let y = _mm_loadu_si128(b.as_ptr().add(i) as *const __m128i);
+            let even = _mm_mul_epu32(x, y);
+            let odd = _mm_mul_epu32(_mm_srli_si128(x, 4), _mm_srli_si128(y, 4));
+            let prod = _mm_unpacklo_epi32(
+                _mm_shuffle_epi32(even, 0b00_00_10_00),
+                _mm_shuffle_epi32(odd, 0b00_00_10_00),
+            );
+            acc = _mm_add_epi32(acc, prod);
+        }
+        let mut lanes = [0i32; 4];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, acc);
+        lanes
+            .iter()
+            .fold(portable::dot_i32(&a[n..], &b[n..]), |s, &x| {
+                s.wrapping_add(x)
+            })
+    }
+    // endregion: --- i32
+
+    // region:    --- i64
+    // 没有 64 位的 mullo (需要 AVX-512), 拆成 lo*lo + (hi*lo + lo*hi) << 32
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn dot_i64_avx2(a: &[i64], b: &[i64]) -> i64 {
+        let n = a.len().min(b.len()) / 4 * 4;
+        let mut acc = _mm256_setzero_si256();
+        for i in (0..n).step_by(4) {
+            let x = _mm256_loadu_si256(a.as_ptr().add(i) as *const __m256i);
+            let y = _mm256_loadu_si256(b.as_ptr().add(i) as *const __m256i);
+            let lo = _mm256_mul_epu32(x, y);
+            let cross = _mm256_add_epi64(
+                _mm256_mul_epu32(_mm256_srli_epi64(x, 32), y),
+                _mm256_mul_epu32(x, _mm256_srli_epi64(y, 32)),
+            );
+            let prod = _mm256_add_epi64(lo, _mm256_slli_epi64(cross, 32));
+            acc = _mm256_add_epi64(acc, prod);
+        }
+        let mut lanes = [0i64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, acc);
+        lanes
+            .iter()
+            .fold(portable::dot_i64(&a[n..], &b[n..]), |s, &x| {
+                s.wrapping_add(x)
+            })
+    }
+
+    #[target_feature(enable = "sse2")]
+    pub(super) unsafe fn dot_i64_sse2(a: &[i64], b: &[i64]) -> i64 {
+        let n = a.len().min(b.len()) / 2 * 2;
+        let mut acc = _mm_setzero_si128();
+        for i in (0..n).step_by(2) {
+            let x = _mm_loadu_si128(a.as_ptr().add(i) as *const __m128i);
+            let y = _mm_loadu_si128(b.as_ptr().add(i) as *const __m128i);
+            let lo = _mm_mul_epu32(x, y);
+            let cross = _mm_add_epi64(
+                _mm_mul_epu32(_mm_srli_epi64(x, 32), y),
+                _mm_mul_epu32(x, _mm_srli_epi64(y, 32)),
+            );
+            let prod = _mm_add_epi64(lo, _mm_slli_epi64(cross, 32));
+            acc = _mm_add_epi64(acc, prod);
+        }
+        let mut lanes = [0i64; 2];
+        _mm_storeu_si128(lanes.as_mut_ptr() as *mut __m128i, acc);
+        lanes
+            .iter()
+            .fold(portable::dot_i64(&a[n..], &b[n..]), |s, &x| {
+                s.wrapping_add(x)
+            })
+    }
+    // endregion: --- i64
+
+    fn tail<T>(a: &[T], b: &[T]) -> T
+    where
+        T: Copy + Default + std::ops::AddAssign + std::ops::Mul<Output = T>,
+    {
+        let mut sum = T::default();
+        for (&x, &y) in a.iter().zip(b) {
+            sum += x * y;
+        }
+        sum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naive<T: Copy + Default + AddAssign + Mul<Output = T>>(a: &[T], b: &[T]) -> T {
+        let mut sum = T::default();
+        for (&x, &y) in a.iter().zip(b) {
+            sum += x * y;
+        }
+        sum
+    }
+
+    #[test]
+    fn test_dot_integers_match_naive() {
+        for len in 0..70i32 {
+            let a = (0..len).map(|i| i * 7 - 100).collect::<Vec<_>>();
+            let b = (0..len).map(|i| 50 - i * 3).collect::<Vec<_>>();
+            assert_eq!(dot(&a, &b), naive(&a, &b));
+
+            let a = a.iter().map(|&x| x as i64 * 1_000_003).collect::<Vec<_>>();
+            let b = b.iter().map(|&x| x as i64 - (1 << 20)).collect::<Vec<_>>();
+            assert_eq!(dot(&a, &b), naive(&a, &b));
+        }
+    }
+
+    #[test]
+    fn test_dot_integers_wrap_on_overflow() {
+        let a = vec![i32::MAX; 9];
+        let b = vec![3; 9];
+        assert_eq!(dot(&a, &b), portable::dot_i32(&a, &b));
+        let a = vec![i64::MIN + 5; 5];
+        assert_eq!(dot(&a, &a), portable::dot_i64(&a, &a));
+    }
+
+    #[test]
+    fn test_dot_floats_close_to_naive() {
+        for len in 0..70 {
+            let a = (0..len)
+                .map(|i| (i as f64 * 0.37).sin())
+                .collect::<Vec<_>>();
+            let b = (0..len)
+                .map(|i| (i as f64 * 0.11).cos())
+                .collect::<Vec<_>>();
+            assert!((dot(&a, &b) - naive(&a, &b)).abs() < 1e-12);
+
+            let a = a.iter().map(|&x| x as f32).collect::<Vec<_>>();
+            let b = b.iter().map(|&x| x as f32).collect::<Vec<_>>();
+            assert!((dot(&a, &b) - naive(&a, &b)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_add_wraps_like_dot() {
+        assert_eq!(add(i32::MAX, 1), i32::MIN);
+        assert_eq!(add(i64::MIN, -1), i64::MAX);
+        assert_eq!(add(1.5f64, 2.0), 3.5);
+        // 自己定义的 i32 不会被当成 i32
+        mod shadow {
+            #[allow(non_camel_case_types, dead_code)]
+            pub struct i32(pub std::primitive::i32);
+        }
+        assert!(!is_primitive::<shadow::i32, i32>());
+        assert!(!is_primitive::<&'static f32, f32>());
+    }
+
+    #[test]
+    fn test_dot_other_types_use_scalar() {
+        let a = [1u8, 2, 3, 4, 5];
+        assert_eq!(dot(&a, &a), 55);
+        assert_eq!(dot_scalar(&a, &a), 55);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_dot_sse2_kernels() {
+        let a = (0..37).map(|i| i as i64 - 11).collect::<Vec<_>>();
+        let expected = naive(&a, &a);
+        let a32 = a.iter().map(|&x| x as i32).collect::<Vec<_>>();
+        let f = a.iter().map(|&x| x as f64).collect::<Vec<_>>();
+        let f32s = a.iter().map(|&x| x as f32).collect::<Vec<_>>();
+        unsafe {
+            assert_eq!(x86::dot_i64_sse2(&a, &a), expected);
+            assert_eq!(x86::dot_i32_sse2(&a32, &a32), expected as i32);
+            assert_eq!(x86::dot_f64_sse2(&f, &f), expected as f64);
+            assert_eq!(x86::dot_f32_sse2(&f32s, &f32s), expected as f32);
+        }
+    }
+}
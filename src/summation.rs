@@ -0,0 +1,202 @@
+use crate::Float;
+
+/// Pairwise summation falls back to a plain loop below this many terms
+const PAIRWISE_BLOCK: usize = 32;
+
+/// How a floating point reduction accumulates its terms
+///
+/// Error bounds for `n` terms, `eps` being the machine epsilon:
+/// `Naive` grows like `n * eps`, `Pairwise` like `log2(n) * eps`, and the
+/// compensated `Kahan`/`Neumaier` stay around `2 * eps` independent of `n`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Summation {
+    /// one running sum, the fastest
+    #[default]
+    Naive,
+    /// Kahan compensated summation
+    Kahan,
+    /// Neumaier's variant of Kahan, also exact when a term is larger than the sum
+    Neumaier,
+    /// recursively sum both halves
+    Pairwise,
+}
+
+// region:    --- impls
+impl Summation {
+    pub fn sum<T: Float>(self, terms: &[T]) -> T {
+        self.sum_by(terms.len(), |i| terms[i])
+    }
+
+    /// Sum `term(0) + term(1) + ... + term(len - 1)`
+    pub(crate) fn sum_by<T: Float>(self, len: usize, term: impl Fn(usize) -> T) -> T {
+        match self {
+            Summation::Naive => naive(0, len, &term),
+            Summation::Kahan => kahan(len, &term),
+            Summation::Neumaier => neumaier(len, &term),
+            Summation::Pairwise => pairwise(0, len, &term),
+        }
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+fn naive<T: Float>(start: usize, end: usize, term: &impl Fn(usize) -> T) -> T {
+    let mut sum = T::default();
+    for i in start..end {
+        sum += term(i);
+    }
+    sum
+}
+
+fn kahan<T: Float>(len: usize, term: &impl Fn(usize) -> T) -> T {
+    let mut sum = T::default();
+    // c 记录上一次加法丢失的低位
+    let mut c = T::default();
+    for i in 0..len {
+        let y = term(i) - c;
+        let t = sum + y;
+        c = (t - sum) - y;
+        sum = t;
+    }
+    sum
+}
+
+fn neumaier<T: Float>(len: usize, term: &impl Fn(usize) -> T) -> T {
+    let mut sum = T::default();
+    let mut c = T::default();
+    for i in 0..len {
+        let x = term(i);
+        let t = sum + x;
+        // 丢失的是绝对值较小的那个操作数的低位
+        if sum.abs() >= x.abs() {
+            c += (sum - t) + x;
+        } else {
+            c += (x - t) + sum;
+        }
+        sum = t;
+    }
+    sum + c
+}
+
+fn pairwise<T: Float>(start: usize, end: usize, term: &impl Fn(usize) -> T) -> T {
+    if end - start <= PAIRWISE_BLOCK {
+        return naive(start, end, term);
+    }
+    let mid = start + (end - start) / 2;
+    pairwise(start, mid, term) + pairwise(mid, end, term)
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dot_product_with, Vector};
+    use rand::distributions::Uniform;
+
+    // double-double 累加, 近似 f128 的精度, 作为 f64 的参考值
+    #[derive(Clone, Copy)]
+    struct DoubleDouble {
+        hi: f64,
+        lo: f64,
+    }
+
+    impl DoubleDouble {
+        fn add(self, x: f64) -> Self {
+            // TwoSum
+            let s = self.hi + x;
+            let bb = s - self.hi;
+            let err = (self.hi - (s - bb)) + (x - bb);
+            let lo = self.lo + err;
+            let hi = s + lo;
+            Self {
+                hi,
+                lo: lo - (hi - s),
+            }
+        }
+
+        fn add_product(self, a: f64, b: f64) -> Self {
+            // TwoProduct with fma, the rounding error of a * b is exact
+            let p = a * b;
+            let e = a.mul_add(b, -p);
+            self.add(p).add(e)
+        }
+    }
+
+    fn reference_dot(a: &[f64], b: &[f64]) -> f64 {
+        a.iter()
+            .zip(b)
+            .fold(DoubleDouble { hi: 0.0, lo: 0.0 }, |acc, (&x, &y)| {
+                acc.add_product(x, y)
+            })
+            .hi
+    }
+
+    #[test]
+    fn test_f32_sum_error_bounds() {
+        // 以 f64 累加作为 f32 的参考值, n 很大时朴素累加的误差明显
+        let n = 1_000_000;
+        let terms = Vector::random_seeded(n, Uniform::new(0.0f32, 1.0), 42).into_vec();
+        let exact = terms.iter().map(|&x| x as f64).sum::<f64>();
+        let err = |s: Summation| ((s.sum(&terms) as f64) - exact).abs() / exact;
+        let eps = f32::EPSILON as f64;
+
+        assert!(err(Summation::Naive) > 10.0 * eps);
+        assert!(err(Summation::Kahan) <= 2.0 * eps);
+        assert!(err(Summation::Neumaier) <= 2.0 * eps);
+        assert!(err(Summation::Pairwise) <= (n as f64).log2() * eps);
+    }
+
+    #[test]
+    fn test_neumaier_handles_large_terms() {
+        // Kahan 在后一项比当前和大时会丢掉补偿, Neumaier 不会
+        let terms = [1.0f64, 1e100, 1.0, -1e100];
+        assert_eq!(Summation::Neumaier.sum(&terms), 2.0);
+        assert_eq!(Summation::Naive.sum(&terms), 0.0);
+    }
+
+    #[test]
+    fn test_f64_dot_against_double_double() {
+        let n = 100_000;
+        let a = (0..n)
+            .map(|i| (i as f64 * 0.618).sin() * 1e3)
+            .collect::<Vec<_>>();
+        let b = (0..n)
+            .map(|i| (i as f64 * 0.577).cos() * 1e-3)
+            .collect::<Vec<_>>();
+        let exact = reference_dot(&a, &b);
+        let magnitude = a.iter().zip(&b).map(|(x, y)| (x * y).abs()).sum::<f64>();
+        let err = |s: Summation| (s.sum_by(n, |i| a[i] * b[i]) - exact).abs() / magnitude;
+        let eps = f64::EPSILON;
+
+        // 乘积本身的舍入误差最多 eps/2, 补偿求和只消除累加误差
+        assert!(err(Summation::Kahan) <= 2.0 * eps);
+        assert!(err(Summation::Neumaier) <= 2.0 * eps);
+        assert!(err(Summation::Pairwise) <= (n as f64).log2() * eps);
+        assert!(err(Summation::Naive) <= n as f64 * eps);
+    }
+
+    #[test]
+    fn test_dot_product_with() -> anyhow::Result<()> {
+        let a = Vector::new([1e16, 1.0, -1e16, 1.0]);
+        let b = Vector::new([1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(dot_product_with(a, b, Summation::Neumaier)?, 2.0);
+
+        let a = Vector::new([1.0f32, 2.0]);
+        let b = Vector::new([1.0f32]);
+        assert!(dot_product_with(a, b, Summation::Pairwise).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_and_small_sums() {
+        for s in [
+            Summation::Naive,
+            Summation::Kahan,
+            Summation::Neumaier,
+            Summation::Pairwise,
+        ] {
+            assert_eq!(s.sum::<f64>(&[]), 0.0);
+            assert_eq!(s.sum(&[1.5f32, 2.5]), 4.0);
+        }
+    }
+}
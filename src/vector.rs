@@ -1,5 +1,19 @@
 use anyhow::Result;
+use rand::{distributions::Distribution, rngs::StdRng, Rng, SeedableRng};
 use std::ops::{Add, AddAssign, Deref, Mul};
+
+use crate::{Float, Summation};
+
+mod norms;
+mod ops;
+mod par;
+mod sparse;
+mod view;
+
+pub use par::dot_product_par;
+pub use sparse::*;
+pub use view::*;
+
 pub struct Vector<T> {
     data: Vec<T>,
 }
@@ -10,6 +24,24 @@ impl<T> Vector<T> {
         Self { data: data.into() }
     }
 
+    /// A vector of `len` elements sampled from `distribution`
+    pub fn random(len: usize, distribution: impl Distribution<T>) -> Self {
+        Self::random_with_rng(len, distribution, &mut rand::thread_rng())
+    }
+
+    /// Like `random`, but the same seed always gives the same vector
+    pub fn random_seeded(len: usize, distribution: impl Distribution<T>, seed: u64) -> Self {
+        Self::random_with_rng(len, distribution, &mut StdRng::seed_from_u64(seed))
+    }
+
+    pub fn random_with_rng<R: Rng + ?Sized>(
+        len: usize,
+        distribution: impl Distribution<T>,
+        rng: &mut R,
+    ) -> Self {
+        Self::new(distribution.sample_iter(rng).take(len).collect::<Vec<_>>())
+    }
+
     pub fn len(&self) -> usize {
         self.data.len()
     }
@@ -18,10 +50,14 @@ impl<T> Vector<T> {
         self.data.is_empty()
     }
 
-    pub fn iter(&self) -> std::slice::Iter<T> {
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
         self.data.iter()
     }
 
+    pub fn view(&self) -> VectorView<'_, T> {
+        VectorView::new(&self.data)
+    }
+
     pub fn into_vec(self) -> Vec<T> {
         self.data
     }
@@ -39,6 +75,10 @@ impl<T> Deref for Vector<T> {
 // region:    --- functions
 // 将 &[T] 转换成 Vec<T>, 因为考虑到多线程, 需要传入一个 owned value
 /// Calculate the dot product of two vectors
+///
+/// f32, f64, i32 and i64 use SIMD kernels picked at runtime. i32 and i64
+/// wrap on overflow whichever kernel runs; other integer types use their own
+/// `Mul` and `AddAssign`, which panic on overflow in debug builds.
 pub fn dot_product<T>(a: Vector<T>, b: Vector<T>) -> Result<T>
 where
     T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T>,
@@ -46,14 +86,15 @@ where
     if a.len() != b.len() {
         return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
     }
-    let mut result = T::default();
-    for i in 0..a.len() {
-        // 使 Vector<T> 可以通过 index 访问
-        // 方法1: 为 Vector<T> 实现 Index trait
-        // 方法2: 为 Vector<T> 实现 Deref trait (recommend)
-        result += a[i] * b[i];
+    dot(&a, &b)
+}
+
+/// Like `dot_product`, accumulating the products with the given `Summation`
+pub fn dot_product_with<T: Float>(a: Vector<T>, b: Vector<T>, summation: Summation) -> Result<T> {
+    if a.len() != b.len() {
+        return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
     }
-    Ok(result)
+    Ok(summation.sum_by(a.len(), |i| a[i] * b[i]))
 }
 
 // endregion: --- functions
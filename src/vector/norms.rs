@@ -0,0 +1,133 @@
+use std::ops::{Mul, Sub};
+
+use anyhow::Result;
+
+use super::{dot, Vector};
+use crate::Float;
+
+// region:    --- norms
+impl<T: Float> Vector<T> {
+    /// Sum of absolute values
+    pub fn norm_l1(&self) -> T {
+        self.iter().fold(T::default(), |acc, &x| acc + x.abs())
+    }
+
+    /// Euclidean length
+    pub fn norm_l2(&self) -> T {
+        dot(self, self).expect("same vector").sqrt()
+    }
+
+    /// Largest absolute value, 0 for an empty vector
+    pub fn norm_inf(&self) -> T {
+        self.iter().fold(T::default(), |acc, &x| {
+            let x = x.abs();
+            if x > acc {
+                x
+            } else {
+                acc
+            }
+        })
+    }
+
+    /// The unit vector in the same direction
+    pub fn normalize(&self) -> Result<Vector<T>> {
+        let norm = self.norm_l2();
+        if norm == T::default() {
+            return Err(anyhow::anyhow!("Vector normalize error: zero vector"));
+        }
+        Ok(self.iter().map(|&x| x / norm).collect())
+    }
+
+    /// Cosine of the angle between two vectors
+    pub fn cosine_similarity(&self, other: &Vector<T>) -> Result<T> {
+        let d = dot(self, other)?;
+        let norms = self.norm_l2() * other.norm_l2();
+        if norms == T::default() {
+            return Err(anyhow::anyhow!("Cosine similarity error: zero vector"));
+        }
+        Ok(d / norms)
+    }
+
+    pub fn euclidean_distance(&self, other: &Vector<T>) -> Result<T> {
+        check_len(self, other, "Euclidean distance")?;
+        let sq = self
+            .iter()
+            .zip(other.iter())
+            .fold(T::default(), |acc, (&x, &y)| {
+                let d = x - y;
+                acc + d * d
+            });
+        Ok(sq.sqrt())
+    }
+
+    pub fn manhattan_distance(&self, other: &Vector<T>) -> Result<T> {
+        check_len(self, other, "Manhattan distance")?;
+        Ok(self
+            .iter()
+            .zip(other.iter())
+            .fold(T::default(), |acc, (&x, &y)| acc + (x - y).abs()))
+    }
+}
+// endregion: --- norms
+
+impl<T> Vector<T>
+where
+    T: Copy + Mul<Output = T> + Sub<Output = T>,
+{
+    /// Cross product, both vectors must have 3 elements
+    pub fn cross(&self, other: &Vector<T>) -> Result<Vector<T>> {
+        if self.len() != 3 || other.len() != 3 {
+            return Err(anyhow::anyhow!("Cross product error: needs two 3-vectors"));
+        }
+        let (a, b) = (&self.data, &other.data);
+        Ok(Vector::new([
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]))
+    }
+}
+
+fn check_len<T>(a: &Vector<T>, b: &Vector<T>, what: &str) -> Result<()> {
+    if a.len() != b.len() {
+        return Err(anyhow::anyhow!("{} error: a.len != b.len", what));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_norms() -> Result<()> {
+        let v = Vector::new([3.0, -4.0]);
+        assert_eq!(v.norm_l1(), 7.0);
+        assert_eq!(v.norm_l2(), 5.0);
+        assert_eq!(v.norm_inf(), 4.0);
+        assert_eq!(v.normalize()?.into_vec(), vec![0.6, -0.8]);
+        assert!(Vector::new([0.0f32, 0.0]).normalize().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_distances() -> Result<()> {
+        let a = Vector::new([1.0, 2.0, 3.0]);
+        let b = Vector::new([4.0, 6.0, 3.0]);
+        assert_eq!(a.euclidean_distance(&b)?, 5.0);
+        assert_eq!(a.manhattan_distance(&b)?, 7.0);
+        assert!((a.cosine_similarity(&(&a * 2.0))? - 1.0).abs() < 1e-12);
+        assert!(a.euclidean_distance(&Vector::new([1.0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_cross() -> Result<()> {
+        let x = Vector::new([1, 0, 0]);
+        let y = Vector::new([0, 1, 0]);
+        assert_eq!(x.cross(&y)?.into_vec(), vec![0, 0, 1]);
+        assert_eq!(y.cross(&x)?.into_vec(), vec![0, 0, -1]);
+        assert!(Vector::new([1, 2]).cross(&y).is_err());
+        Ok(())
+    }
+}
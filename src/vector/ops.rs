@@ -0,0 +1,198 @@
+use std::{
+    ops::{Add, AddAssign, DerefMut, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign},
+    slice::SliceIndex,
+};
+
+use super::Vector;
+
+// 运算符没法返回 Result, 长度不一致时和 Matrix * Matrix 一样直接 panic
+
+// region:    --- element-wise
+macro_rules! impl_binary_op {
+    ($op:ident, $method:ident, $assign:ident, $assign_method:ident) => {
+        impl<T: Copy + $op<Output = T>> $op for Vector<T> {
+            type Output = Vector<T>;
+
+            fn $method(self, rhs: Self) -> Self::Output {
+                (&self).$method(&rhs)
+            }
+        }
+
+        impl<T: Copy + $op<Output = T>> $op<&Vector<T>> for &Vector<T> {
+            type Output = Vector<T>;
+
+            fn $method(self, rhs: &Vector<T>) -> Self::Output {
+                assert_len(self, rhs, stringify!($method));
+                self.iter()
+                    .zip(rhs.iter())
+                    .map(|(&x, &y)| x.$method(y))
+                    .collect()
+            }
+        }
+
+        impl<T: Copy + $assign> $assign<&Vector<T>> for Vector<T> {
+            fn $assign_method(&mut self, rhs: &Vector<T>) {
+                assert_len(self, rhs, stringify!($assign_method));
+                for (x, &y) in self.data.iter_mut().zip(rhs.iter()) {
+                    x.$assign_method(y);
+                }
+            }
+        }
+
+        impl<T: Copy + $assign> $assign for Vector<T> {
+            fn $assign_method(&mut self, rhs: Self) {
+                self.$assign_method(&rhs);
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign);
+
+/// Scale every element
+impl<T: Copy + Mul<Output = T>> Mul<T> for Vector<T> {
+    type Output = Vector<T>;
+
+    fn mul(mut self, rhs: T) -> Self::Output {
+        for x in self.data.iter_mut() {
+            *x = *x * rhs;
+        }
+        self
+    }
+}
+
+impl<T: Copy + Mul<Output = T>> Mul<T> for &Vector<T> {
+    type Output = Vector<T>;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        self.iter().map(|&x| x * rhs).collect()
+    }
+}
+
+impl<T: Copy + MulAssign> MulAssign<T> for Vector<T> {
+    fn mul_assign(&mut self, rhs: T) {
+        for x in self.data.iter_mut() {
+            *x *= rhs;
+        }
+    }
+}
+
+impl<T: Copy + Neg<Output = T>> Neg for Vector<T> {
+    type Output = Vector<T>;
+
+    fn neg(mut self) -> Self::Output {
+        for x in self.data.iter_mut() {
+            *x = -*x;
+        }
+        self
+    }
+}
+
+impl<T: Copy + Neg<Output = T>> Neg for &Vector<T> {
+    type Output = Vector<T>;
+
+    fn neg(self) -> Self::Output {
+        self.iter().map(|&x| -x).collect()
+    }
+}
+// endregion: --- element-wise
+
+// region:    --- collections
+impl<T> FromIterator<T> for Vector<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Vector::new(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T> IntoIterator for Vector<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Vector<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a mut Vector<T> {
+    type Item = &'a mut T;
+    type IntoIter = std::slice::IterMut<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter_mut()
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> Index<I> for Vector<T> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.data[index]
+    }
+}
+
+impl<T, I: SliceIndex<[T]>> IndexMut<I> for Vector<T> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.data[index]
+    }
+}
+
+impl<T> DerefMut for Vector<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+// endregion: --- collections
+
+fn assert_len<T>(a: &Vector<T>, b: &Vector<T>, op: &str) {
+    assert_eq!(a.len(), b.len(), "Vector {} error: a.len != b.len", op);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_ops() {
+        let a = Vector::new([1, 2, 3]);
+        let b = Vector::new([10, 20, 30]);
+        assert_eq!((&a + &b).into_vec(), vec![11, 22, 33]);
+        assert_eq!((&b - &a).into_vec(), vec![9, 18, 27]);
+        assert_eq!((&a * 2).into_vec(), vec![2, 4, 6]);
+        assert_eq!((-&a).into_vec(), vec![-1, -2, -3]);
+
+        let mut c = a + b;
+        c -= Vector::new([1, 1, 1]);
+        c += &Vector::new([0, 0, 100]);
+        c *= 10;
+        assert_eq!(c.into_vec(), vec![100, 210, 1320]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vector add error")]
+    fn test_vector_add_len_mismatch() {
+        let _ = Vector::new([1, 2]) + Vector::new([1, 2, 3]);
+    }
+
+    #[test]
+    fn test_vector_collections() {
+        let mut v = (1..=4).collect::<Vector<i32>>();
+        v[0] = 10;
+        v.push(5);
+        for x in &mut v {
+            *x += 1;
+        }
+        assert_eq!(&v[1..3], &[3, 4]);
+        assert_eq!((&v).into_iter().sum::<i32>(), 29);
+        assert_eq!(v.into_iter().collect::<Vec<_>>(), vec![11, 3, 4, 5, 6]);
+    }
+}
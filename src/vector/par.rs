@@ -0,0 +1,135 @@
+use std::ops::{Add, AddAssign, Mul};
+
+use anyhow::Result;
+
+use super::Vector;
+use crate::{
+    parallel::{par_chunks, par_ranges, sum_of},
+    simd, Float,
+};
+
+// 按 chunk 切分到多个线程, 每个线程算出部分结果再合并;
+// 小于 PAR_THRESHOLD 时 par_ranges 直接在当前线程上跑
+
+// region:    --- reductions
+impl<T> Vector<T>
+where
+    T: Copy + Send + Sync,
+{
+    /// Sum of all elements, in parallel for large vectors
+    pub fn sum_par(&self) -> T
+    where
+        T: Default + Add<Output = T>,
+    {
+        par_chunks(self, |chunk| sum_of(chunk.iter().copied()))
+            .into_iter()
+            .fold(T::default(), |acc, x| acc + x)
+    }
+
+    /// Largest element, in parallel for large vectors
+    pub fn max_par(&self) -> Option<T>
+    where
+        T: PartialOrd,
+    {
+        par_chunks(self, |chunk| chunk.iter().copied().reduce(larger))
+            .into_iter()
+            .flatten()
+            .reduce(larger)
+    }
+}
+
+impl<T: Float> Vector<T> {
+    pub fn norm_l1_par(&self) -> T {
+        par_chunks(self, |chunk| sum_of(chunk.iter().map(|x| x.abs())))
+            .into_iter()
+            .fold(T::default(), |acc, x| acc + x)
+    }
+
+    pub fn norm_l2_par(&self) -> T {
+        par_chunks(self, |chunk| simd::dot(chunk, chunk))
+            .into_iter()
+            .fold(T::default(), |acc, x| acc + x)
+            .sqrt()
+    }
+
+    pub fn norm_inf_par(&self) -> T {
+        par_chunks(self, |chunk| {
+            chunk.iter().map(|x| x.abs()).fold(T::default(), larger)
+        })
+        .into_iter()
+        .fold(T::default(), larger)
+    }
+}
+// endregion: --- reductions
+
+// region:    --- functions
+/// Like `dot_product`, but splits large vectors into chunks across threads
+/// and adds up the partial sums
+pub fn dot_product_par<T>(a: &Vector<T>, b: &Vector<T>) -> Result<T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Send + Sync,
+{
+    if a.len() != b.len() {
+        return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
+    }
+    // 每个下标读两个元素
+    Ok(
+        par_ranges(a.len(), 2, |range| simd::dot(&a[range.clone()], &b[range]))
+            .into_iter()
+            .fold(T::default(), simd::add),
+    )
+}
+
+// 相等时保留前一个
+fn larger<T: PartialOrd>(a: T, b: T) -> T {
+    if b > a {
+        b
+    } else {
+        a
+    }
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{dot_product, parallel::PAR_THRESHOLD};
+
+    #[test]
+    fn test_dot_product_par_matches_sequential() -> Result<()> {
+        let len = PAR_THRESHOLD * 3 + 7;
+        let a = (0..len).map(|i| (i % 100) as i64).collect::<Vector<_>>();
+        let b = (0..len).map(|i| (i % 7) as i64 - 3).collect::<Vector<_>>();
+        let expected = dot_product(Vector::new(a.as_slice()), Vector::new(b.as_slice()))?;
+        assert_eq!(dot_product_par(&a, &b)?, expected);
+        assert!(dot_product_par(&a, &Vector::new([1i64])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_product_par_wraps_like_sequential() -> Result<()> {
+        // 分块的部分和相加时也按 wrapping 处理, 和顺序版本结果一致
+        let a = Vector::new(vec![i32::MAX; PAR_THRESHOLD * 2 + 3]);
+        let expected = dot_product(Vector::new(a.as_slice()), Vector::new(a.as_slice()))?;
+        assert_eq!(dot_product_par(&a, &a)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_reductions_par() {
+        let len = PAR_THRESHOLD * 2 + 3;
+        let v = (0..len)
+            .map(|i| (i % 1000) as i64 - 500)
+            .collect::<Vector<_>>();
+        assert_eq!(v.sum_par(), v.iter().sum::<i64>());
+        assert_eq!(v.max_par(), Some(499));
+        assert_eq!(Vector::<i64>::new([]).max_par(), None);
+
+        let f = (0..len)
+            .map(|i| if i == 12345 { -8.0 } else { 0.5 })
+            .collect::<Vector<f64>>();
+        assert_eq!(f.norm_inf_par(), 8.0);
+        assert_eq!(f.norm_l1_par(), f.norm_l1());
+        assert!((f.norm_l2_par() - f.norm_l2()).abs() < 1e-9);
+    }
+}
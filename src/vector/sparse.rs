@@ -0,0 +1,240 @@
+use std::{
+    cmp::Ordering,
+    ops::{AddAssign, Mul},
+};
+
+use anyhow::Result;
+
+use super::{AsVectorView, Vector};
+use crate::Float;
+
+/// A mostly-zero vector of length `len`, stored as index/value pairs sorted by index
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseVector<T> {
+    len: usize,
+    indices: Vec<usize>,
+    values: Vec<T>,
+}
+
+// region:    --- impls
+impl<T> SparseVector<T> {
+    /// Build from `(index, value)` pairs in any order
+    pub fn new(len: usize, pairs: impl IntoIterator<Item = (usize, T)>) -> Result<Self> {
+        let mut pairs = pairs.into_iter().collect::<Vec<_>>();
+        pairs.sort_by_key(|(idx, _)| *idx);
+        if let Some((idx, _)) = pairs.last() {
+            if *idx >= len {
+                return Err(anyhow::anyhow!(
+                    "SparseVector error: index {} out of range for len {}",
+                    idx,
+                    len
+                ));
+            }
+        }
+        if let Some(w) = pairs.windows(2).find(|w| w[0].0 == w[1].0) {
+            return Err(anyhow::anyhow!(
+                "SparseVector error: duplicate index {}",
+                w[0].0
+            ));
+        }
+        let (indices, values) = pairs.into_iter().unzip();
+        Ok(Self {
+            len,
+            indices,
+            values,
+        })
+    }
+
+    /// The non-zero elements of a dense vector
+    pub fn from_dense(dense: &Vector<T>) -> Self
+    where
+        T: Copy + Default + PartialEq,
+    {
+        let zero = T::default();
+        let (indices, values) = dense
+            .iter()
+            .enumerate()
+            .filter(|(_, &x)| x != zero)
+            .map(|(idx, &x)| (idx, x))
+            .unzip();
+        Self {
+            len: dense.len(),
+            indices,
+            values,
+        }
+    }
+
+    pub fn to_dense(&self) -> Vector<T>
+    where
+        T: Copy + Default,
+    {
+        let mut data = vec![T::default(); self.len];
+        for (idx, &x) in self.iter() {
+            data[idx] = x;
+        }
+        Vector::new(data)
+    }
+
+    /// Logical length, zeros included
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Number of stored elements
+    pub fn nnz(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// The stored element at `idx`, `None` for an implicit zero
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        let pos = self.indices.binary_search(&idx).ok()?;
+        Some(&self.values[pos])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.indices.iter().copied().zip(self.values.iter())
+    }
+}
+
+impl<T> SparseVector<T>
+where
+    T: Copy + Default + AddAssign + Mul<Output = T>,
+{
+    /// Dot product with a dense `Vector`, `VectorView` or slice, touching only
+    /// the stored elements
+    pub fn dot_dense<A: AsVectorView<T> + ?Sized>(&self, dense: &A) -> Result<T> {
+        let dense = dense.as_view();
+        if dense.len() != self.len {
+            return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
+        }
+        let mut sum = T::default();
+        for (idx, &x) in self.iter() {
+            sum += x * dense[idx];
+        }
+        Ok(sum)
+    }
+
+    /// Dot product of two sparse vectors, a merge-join over the sorted indices
+    pub fn dot(&self, other: &SparseVector<T>) -> Result<T> {
+        if self.len != other.len {
+            return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
+        }
+        let (mut i, mut j) = (0, 0);
+        let mut sum = T::default();
+        while i < self.nnz() && j < other.nnz() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                Ordering::Less => i += 1,
+                Ordering::Greater => j += 1,
+                Ordering::Equal => {
+                    sum += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        Ok(sum)
+    }
+}
+
+impl<T: Float> SparseVector<T> {
+    pub fn norm_l1(&self) -> T {
+        self.values
+            .iter()
+            .fold(T::default(), |acc, &x| acc + x.abs())
+    }
+
+    pub fn norm_l2(&self) -> T {
+        self.values
+            .iter()
+            .fold(T::default(), |acc, &x| acc + x * x)
+            .sqrt()
+    }
+
+    pub fn norm_inf(&self) -> T {
+        self.values.iter().fold(T::default(), |acc, &x| {
+            let x = x.abs();
+            if x > acc {
+                x
+            } else {
+                acc
+            }
+        })
+    }
+}
+
+impl<T: Copy + Default + PartialEq> From<&Vector<T>> for SparseVector<T> {
+    fn from(dense: &Vector<T>) -> Self {
+        Self::from_dense(dense)
+    }
+}
+
+impl<T: Copy + Default> From<&SparseVector<T>> for Vector<T> {
+    fn from(sparse: &SparseVector<T>) -> Self {
+        sparse.to_dense()
+    }
+}
+// endregion: --- impls
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_vector_new() -> Result<()> {
+        let v = SparseVector::new(10, [(7, 3), (2, 1)])?;
+        assert_eq!(v.indices(), &[2, 7]);
+        assert_eq!(v.values(), &[1, 3]);
+        assert_eq!((v.len(), v.nnz()), (10, 2));
+        assert_eq!(v.get(7), Some(&3));
+        assert_eq!(v.get(3), None);
+
+        assert!(SparseVector::new(5, [(5, 1)]).is_err());
+        assert!(SparseVector::new(5, [(1, 1), (1, 2)]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_vector_conversions() {
+        let dense = Vector::new([0, 4, 0, 0, 5]);
+        let sparse = SparseVector::from(&dense);
+        assert_eq!(sparse.indices(), &[1, 4]);
+        assert_eq!(Vector::from(&sparse).into_vec(), vec![0, 4, 0, 0, 5]);
+    }
+
+    #[test]
+    fn test_sparse_vector_dot() -> Result<()> {
+        let a = SparseVector::new(8, [(0, 2), (3, 5), (6, 1)])?;
+        let b = SparseVector::new(8, [(3, 4), (5, 9), (6, 10)])?;
+        assert_eq!(a.dot(&b)?, 30);
+        assert_eq!(b.dot(&a)?, 30);
+
+        let dense = Vector::new([1, 1, 1, 2, 1, 1, 3, 1]);
+        assert_eq!(a.dot_dense(&dense)?, 15);
+        assert_eq!(a.dot_dense(&dense)?, a.dot(&SparseVector::from(&dense))?);
+        assert!(a.dot_dense(&[1, 2]).is_err());
+        assert!(a.dot(&SparseVector::new(3, [])?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sparse_vector_norms() -> Result<()> {
+        let v = SparseVector::new(100, [(10, 3.0), (90, -4.0)])?;
+        assert_eq!(v.norm_l1(), 7.0);
+        assert_eq!(v.norm_l2(), 5.0);
+        assert_eq!(v.norm_inf(), 4.0);
+        assert_eq!(v.norm_l2(), v.to_dense().norm_l2());
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    iter::StepBy,
+    ops::{AddAssign, Index, Mul},
+    slice,
+};
+
+use anyhow::Result;
+
+use super::Vector;
+use crate::simd;
+
+/// A borrowed vector: `len` elements of a slice, `stride` apart
+///
+/// A column of a row-major matrix is a view with `stride == col`, so it can
+/// take part in a dot product without being copied out.
+#[derive(Debug)]
+pub struct VectorView<'a, T> {
+    // 只保留从第一个到最后一个元素的那一段
+    data: &'a [T],
+    len: usize,
+    stride: usize,
+}
+
+/// Anything that can be borrowed as a `VectorView`
+pub trait AsVectorView<T> {
+    fn as_view(&self) -> VectorView<'_, T>;
+}
+
+// region:    --- impls
+impl<'a, T> VectorView<'a, T> {
+    pub fn new(data: &'a [T]) -> Self {
+        Self {
+            data,
+            len: data.len(),
+            stride: 1,
+        }
+    }
+
+    /// `len` elements starting at `data[0]`, `stride` apart
+    pub fn strided(data: &'a [T], len: usize, stride: usize) -> Result<Self> {
+        if stride == 0 {
+            return Err(anyhow::anyhow!("VectorView error: stride is 0"));
+        }
+        let span = match len {
+            0 => 0,
+            _ => (len - 1) * stride + 1,
+        };
+        if span > data.len() {
+            return Err(anyhow::anyhow!(
+                "VectorView error: {} elements with stride {} need {} items, slice has {}",
+                len,
+                stride,
+                span,
+                data.len()
+            ));
+        }
+        Ok(Self {
+            data: &data[..span],
+            len,
+            stride,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn get(&self, i: usize) -> Option<&'a T> {
+        if i < self.len {
+            Some(&self.data[i * self.stride])
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> StepBy<slice::Iter<'a, T>> {
+        self.data.iter().step_by(self.stride)
+    }
+
+    /// The elements as one slice, if they are contiguous
+    pub fn as_slice(&self) -> Option<&'a [T]> {
+        if self.stride == 1 || self.len <= 1 {
+            Some(self.data)
+        } else {
+            None
+        }
+    }
+
+    pub fn to_vector(&self) -> Vector<T>
+    where
+        T: Clone,
+    {
+        Vector::new(self.iter().cloned().collect::<Vec<_>>())
+    }
+}
+
+// derive 会要求 T: Clone, 而 view 本身只是一个引用
+impl<T> Clone for VectorView<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for VectorView<'_, T> {}
+
+impl<T> Index<usize> for VectorView<'_, T> {
+    type Output = T;
+
+    fn index(&self, i: usize) -> &T {
+        self.get(i).expect("VectorView index out of range")
+    }
+}
+
+impl<T> AsVectorView<T> for VectorView<'_, T> {
+    fn as_view(&self) -> VectorView<'_, T> {
+        *self
+    }
+}
+
+impl<T> AsVectorView<T> for Vector<T> {
+    fn as_view(&self) -> VectorView<'_, T> {
+        VectorView::new(self)
+    }
+}
+
+impl<T> AsVectorView<T> for [T] {
+    fn as_view(&self) -> VectorView<'_, T> {
+        VectorView::new(self)
+    }
+}
+
+impl<T> AsVectorView<T> for Vec<T> {
+    fn as_view(&self) -> VectorView<'_, T> {
+        VectorView::new(self)
+    }
+}
+
+impl<T, const N: usize> AsVectorView<T> for [T; N] {
+    fn as_view(&self) -> VectorView<'_, T> {
+        VectorView::new(self)
+    }
+}
+// endregion: --- impls
+
+// region:    --- functions
+/// Dot product of any two of `Vector`, `VectorView`, `Vec` or slices
+///
+/// Contiguous inputs use the same SIMD kernels as `dot_product`; strided
+/// views are copied into contiguous buffers first, so overflow behaves the
+/// same either way.
+pub fn dot<T, A, B>(a: &A, b: &B) -> Result<T>
+where
+    T: Copy + Default + AddAssign + Mul<Output = T>,
+    A: AsVectorView<T> + ?Sized,
+    B: AsVectorView<T> + ?Sized,
+{
+    let (a, b) = (a.as_view(), b.as_view());
+    if a.len() != b.len() {
+        return Err(anyhow::anyhow!("Dot product error: a.len != b.len"));
+    }
+    if let (Some(a), Some(b)) = (a.as_slice(), b.as_slice()) {
+        return Ok(simd::dot(a, b));
+    }
+    Ok(simd::dot(&a.to_vector(), &b.to_vector()))
+}
+// endregion: --- functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strided_dot_wraps_like_contiguous() -> Result<()> {
+        let data = [i64::MAX, 0, i64::MAX, 0, 3, 0];
+        let strided = VectorView::strided(&data, 3, 2)?;
+        let contiguous = [i64::MAX, i64::MAX, 3];
+        assert_eq!(dot(&strided, &strided)?, dot(&contiguous, &contiguous)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_strided_view() -> Result<()> {
+        let data = [1, 2, 3, 4, 5, 6, 7];
+        let view = VectorView::strided(&data, 3, 3)?;
+        assert_eq!(view.len(), 3);
+        assert_eq!(view.iter().copied().collect::<Vec<_>>(), vec![1, 4, 7]);
+        assert_eq!(view[1], 4);
+        assert_eq!(view.get(3), None);
+        assert!(view.as_slice().is_none());
+        assert_eq!(view.to_vector().into_vec(), vec![1, 4, 7]);
+
+        assert!(VectorView::strided(&data, 4, 3).is_err());
+        assert!(VectorView::strided(&data, 2, 0).is_err());
+        assert!(VectorView::strided(&data, 0, 5)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_mixes_owned_views_and_slices() -> Result<()> {
+        let owned = Vector::new([1, 2, 3]);
+        let matrix = [1, 0, 0, 0, 1, 0, 0, 0, 1];
+        let column = VectorView::strided(&matrix[1..], 3, 3)?;
+        assert_eq!(dot(&owned, &[4, 5, 6])?, 32);
+        assert_eq!(dot(&owned[..], &vec![4, 5, 6])?, 32);
+        assert_eq!(dot(&owned, &column)?, 2);
+        assert_eq!(dot(&column, &VectorView::strided(&matrix, 3, 3)?)?, 0);
+        assert_eq!(dot(&VectorView::strided(&matrix, 3, 3)?, &owned)?, 1);
+        assert!(dot(&owned, &[1, 2]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+use std::{
+    io::{BufRead, BufReader},
+    net::SocketAddr,
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{anyhow, Result};
+use concurrency::{multiply_seq, Coordinator, Layout, Matrix};
+use rand::distributions::Uniform;
+use tokio::{io::AsyncReadExt, net::TcpListener};
+
+// 一个真实的 worker 进程, drop 时 kill 掉
+struct WorkerProcess {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl WorkerProcess {
+    fn spawn() -> Result<Self> {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_matrix_worker"))
+            .arg("127.0.0.1:0")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("no stdout"))?;
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line)?;
+        let addr = line
+            .trim()
+            .strip_prefix("listening on ")
+            .ok_or_else(|| anyhow!("unexpected worker output: {:?}", line))?
+            .parse()?;
+        Ok(Self { child, addr })
+    }
+}
+
+impl Drop for WorkerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// 接受连接, 读到第一个 job 之后直接断开, 模拟中途掉线的 worker
+async fn flaky_worker() -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buf = [0u8; 16];
+            let _ = stream.read(&mut buf).await;
+        }
+    });
+    Ok(addr)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn distributed_multiply_matches_sequential() -> Result<()> {
+    let workers = (0..3)
+        .map(|_| WorkerProcess::spawn())
+        .collect::<Result<Vec<_>>>()?;
+    let coordinator = Coordinator::new(workers.iter().map(|w| w.addr)).tile_size(8);
+
+    let a = Matrix::random_seeded(37, 23, Uniform::new(-50i32, 50), 1);
+    let b = Matrix::random_seeded(23, 41, Uniform::new(-50i32, 50), 2).to_layout(Layout::ColMajor);
+    let c = coordinator.multiply(&a, &b).await?;
+    assert_eq!(c, multiply_seq(&a, &b)?);
+
+    let a = Matrix::random_seeded(20, 30, Uniform::new(-1.0f64, 1.0), 3);
+    let b = Matrix::random_seeded(30, 10, Uniform::new(-1.0f64, 1.0), 4);
+    let c = coordinator.multiply(&a, &b).await?;
+    let expected = multiply_seq(&a, &b)?;
+    for (x, y) in c.data().iter().zip(expected.data()) {
+        assert!((x - y).abs() < 1e-12);
+    }
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn distributed_multiply_reassigns_lost_tiles() -> Result<()> {
+    let healthy = WorkerProcess::spawn()?;
+    let dead = WorkerProcess::spawn()?;
+    let dead_addr = dead.addr;
+    drop(dead);
+    let flaky = flaky_worker().await?;
+
+    let coordinator = Coordinator::new([flaky, dead_addr, healthy.addr]).tile_size(4);
+    let a = Matrix::random_seeded(16, 9, Uniform::new(0i64, 100), 5);
+    let b = Matrix::random_seeded(9, 12, Uniform::new(0i64, 100), 6);
+    let c = coordinator.multiply(&a, &b).await?;
+    assert_eq!(c, multiply_seq(&a, &b)?);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn distributed_multiply_fails_without_workers() -> Result<()> {
+    let flaky = flaky_worker().await?;
+    let a = Matrix::new([1, 2, 3, 4], 2, 2);
+    let b = Matrix::new([1, 2, 3, 4], 2, 2);
+    assert!(Coordinator::new([flaky]).multiply(&a, &b).await.is_err());
+    assert!(Coordinator::new([]).multiply(&a, &b).await.is_err());
+
+    let c = Matrix::new([1, 2, 3], 3, 1);
+    assert!(Coordinator::new([flaky]).multiply(&a, &c).await.is_err());
+    Ok(())
+}
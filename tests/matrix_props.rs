@@ -0,0 +1,103 @@
+use concurrency::{
+    multiply, multiply_seq, multiply_with_threads, Layout, Matrix, MatrixPool, Scheduler, Vector,
+};
+use proptest::prelude::*;
+use rand::distributions::Uniform;
+
+fn identity(n: usize) -> Matrix<i64> {
+    let data = (0..n * n)
+        .map(|idx| if idx / n == idx % n { 1 } else { 0 })
+        .collect::<Vec<_>>();
+    Matrix::new(data, n, n)
+}
+
+fn layout(col_major: bool) -> Layout {
+    if col_major {
+        Layout::ColMajor
+    } else {
+        Layout::RowMajor
+    }
+}
+
+fn approx_eq(a: &Matrix<f64>, b: &Matrix<f64>, tol: f64) -> bool {
+    a.row() == b.row()
+        && a.col() == b.col()
+        && (0..a.row()).all(|i| {
+            (0..a.col()).all(|j| {
+                let (x, y) = (a.get(i, j).unwrap(), b.get(i, j).unwrap());
+                (x - y).abs() <= tol * (1.0 + x.abs().max(y.abs()))
+            })
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn parallel_multiply_matches_sequential(
+        m in 1usize..12,
+        n in 1usize..12,
+        p in 1usize..12,
+        threads in 1usize..9,
+        la in any::<bool>(),
+        lb in any::<bool>(),
+        seed in any::<u64>(),
+    ) {
+        let dist = Uniform::new_inclusive(-100i64, 100);
+        let a = Matrix::random_seeded(m, n, dist, seed).to_layout(layout(la));
+        let b = Matrix::random_seeded(n, p, dist, seed.wrapping_add(1)).to_layout(layout(lb));
+        let expected = multiply_seq(&a, &b).unwrap();
+        let c = multiply_with_threads(&a, &b, threads).unwrap();
+        prop_assert_eq!(c.layout(), expected.layout());
+        prop_assert_eq!(c.data(), expected.data());
+
+        let pool = MatrixPool::new(threads).scheduler(Scheduler::WorkStealing);
+        let c = pool.multiply(&a, &b).unwrap();
+        prop_assert_eq!(c.data(), expected.data());
+    }
+
+    #[test]
+    fn multiply_is_associative_within_tolerance(
+        m in 1usize..8,
+        n in 1usize..8,
+        p in 1usize..8,
+        q in 1usize..8,
+        seed in any::<u64>(),
+    ) {
+        let dist = Uniform::new(-1.0f64, 1.0);
+        let a = Matrix::random_seeded(m, n, dist, seed);
+        let b = Matrix::random_seeded(n, p, dist, seed.wrapping_add(1));
+        let c = Matrix::random_seeded(p, q, dist, seed.wrapping_add(2));
+        let left = multiply(&multiply(&a, &b).unwrap(), &c).unwrap();
+        let right = multiply(&a, &multiply(&b, &c).unwrap()).unwrap();
+        prop_assert!(approx_eq(&left, &right, 1e-12));
+    }
+
+    #[test]
+    fn identity_is_neutral(m in 1usize..10, n in 1usize..10, seed in any::<u64>()) {
+        let a = Matrix::random_seeded(m, n, Uniform::new(-1000i64, 1000), seed);
+        prop_assert_eq!(multiply(&identity(m), &a).unwrap(), Matrix::new(a.data(), m, n));
+        prop_assert_eq!(multiply(&a, &identity(n)).unwrap(), Matrix::new(a.data(), m, n));
+    }
+
+    #[test]
+    fn shape_mismatch_is_an_error(m in 1usize..6, n in 1usize..6, k in 1usize..6) {
+        prop_assume!(n != k);
+        let a = Matrix::random(m, n, Uniform::new(0i64, 10));
+        let b = Matrix::random(k, m, Uniform::new(0i64, 10));
+        prop_assert!(multiply(&a, &b).is_err());
+    }
+}
+
+#[test]
+fn random_is_reproducible_with_seed() {
+    let dist = Uniform::new(0.0f32, 1.0);
+    let a = Matrix::random_seeded(4, 5, dist, 42);
+    let b = Matrix::random_seeded(4, 5, dist, 42);
+    assert_eq!(a, b);
+    assert_ne!(a, Matrix::random_seeded(4, 5, dist, 43));
+
+    let v = Vector::random_seeded(16, dist, 7);
+    assert_eq!(v.len(), 16);
+    assert_eq!(v.into_vec(), Vector::random_seeded(16, dist, 7).into_vec());
+}